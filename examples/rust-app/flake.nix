@@ -104,7 +104,7 @@
               depsSrc = sources.deps;
               cargoToml = ./Cargo.toml;
               inherit rev cargoTestExtraArgs;
-              runTests = true;
+              mode = "test";
             };
 
           # Pre-built test derivations, reused in both packages and checks
@@ -116,6 +116,18 @@
             cargoTestExtraArgs = "--lib";
           };
 
+          # Run the test suite on stable, beta, nightly and the MSRV declared
+          # in Cargo.toml, yielding checks named test-stable, test-msrv, ...
+          toolchainChecks = lib.mkToolchainChecks {
+            args = {
+              src = sources.test;
+              depsSrc = sources.deps;
+              cargoToml = ./Cargo.toml;
+              inherit rev;
+              mode = "test";
+            };
+          };
+
         in
         {
           # Packages that can be built with `nix build`
@@ -180,10 +192,10 @@
 
           # Checks that run with `nix flake check`
           checks = {
+            # test-nightly is provided by toolchainChecks below
             inherit
               unit-tests
               integration-tests
-              test-nightly
               unit-tests-nightly
               ;
 
@@ -193,7 +205,7 @@
               depsSrc = sources.deps;
               cargoToml = ./Cargo.toml;
               inherit rev;
-              runClippy = true;
+              mode = "clippy";
             };
 
             # Compile benchmarks (without running)
@@ -202,7 +214,7 @@
               depsSrc = sources.deps;
               cargoToml = ./Cargo.toml;
               inherit rev;
-              buildBench = true;
+              mode = "bench-compile";
             };
 
             # Code coverage (outputs LCOV report)
@@ -211,7 +223,7 @@
               depsSrc = sources.deps;
               cargoToml = ./Cargo.toml;
               inherit rev;
-              runCoverage = true;
+              mode = "coverage";
               # Override defaults if needed:
               # cargoLlvmCovExtraArgs = "--html --output-dir $out";
               # cargoLlvmCovCommand = "test";
@@ -219,7 +231,8 @@
 
             # Formatting check
             formatting = config.treefmt.build.check self;
-          };
+          }
+          // toolchainChecks;
 
           # Code formatting configuration
           treefmt = lib.mkTreefmtConfig {
@@ -0,0 +1,13 @@
+//! Compiles `cpp/greeting.cpp` into a static library with the `cc` crate.
+//!
+//! cc takes the compiler and archiver from `CXX_<target>` / `AR_<target>`, and
+//! links the C++ runtime selected by `CXXSTDLIB_<target>`.
+
+fn main() {
+    cc::Build::new()
+        .cpp(true)
+        .file("cpp/greeting.cpp")
+        .compile("greeting");
+
+    println!("cargo:rerun-if-changed=cpp/greeting.cpp");
+}
@@ -0,0 +1,17 @@
+// Uses the C++ standard library (std::string, std::ostringstream), so the
+// binary has to link the C++ runtime.
+#include <cstring>
+#include <sstream>
+#include <string>
+
+extern "C" size_t greeting(const char *name, char *buf, size_t len) {
+    std::ostringstream out;
+    out << "Hello from C++, " << std::string(name) << "!";
+    const std::string message = out.str();
+    if (len > 0) {
+        size_t n = message.size() < len - 1 ? message.size() : len - 1;
+        std::memcpy(buf, message.data(), n);
+        buf[n] = '\0';
+    }
+    return message.size();
+}
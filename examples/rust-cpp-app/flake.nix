@@ -0,0 +1,112 @@
+{
+  description = "Example Rust application with C++ code, linked fully static on musl";
+
+  inputs = {
+    nixpkgs.url = "github:NixOS/nixpkgs/release-25.11";
+
+    # Use the parent directory (nix-lib) as the library source
+    # In a real project, you would use:
+    # nix-lib.url = "github:hoprnet/nix-lib";
+    nix-lib.url = "path:../..";
+
+    flake-parts.url = "github:hercules-ci/flake-parts";
+  };
+
+  outputs =
+    inputs@{
+      nixpkgs,
+      nix-lib,
+      flake-parts,
+      ...
+    }:
+    flake-parts.lib.mkFlake { inherit inputs; } {
+      systems = [
+        "x86_64-linux"
+        "aarch64-linux"
+      ];
+
+      perSystem =
+        {
+          system,
+          pkgs,
+          ...
+        }:
+        let
+          lib = nix-lib.lib.${system};
+
+          # Static musl builders link the C++ runtime (libstdc++) statically
+          builders = lib.mkRustBuilders {
+            targets = [
+              { name = "local"; }
+              {
+                name = "x86_64-linux";
+                crossSystem = "x86_64-unknown-linux-musl";
+                isStatic = true;
+              }
+              {
+                name = "aarch64-linux";
+                crossSystem = "aarch64-unknown-linux-musl";
+                isStatic = true;
+              }
+            ];
+          };
+
+          src = lib.mkSrc {
+            root = ./.;
+            fs = nixpkgs.lib.fileset;
+            extraExtensions = [ "cpp" ];
+          };
+          depsSrc = lib.mkDepsSrc {
+            root = ./.;
+            fs = nixpkgs.lib.fileset;
+          };
+
+          # Cross binaries are executed under QEMU through $TARGET_RUNNER
+          mkPackage =
+            builder:
+            builder.callPackage lib.mkRustPackage {
+              inherit src depsSrc;
+              cargoToml = ./Cargo.toml;
+              installCheckCommand = "$TARGET_RUNNER $out/bin/rust-cpp-app";
+            };
+
+          # Fails unless the binary is fully static: no program interpreter and
+          # no shared library dependencies
+          mkStaticCheck =
+            name: package:
+            pkgs.runCommand "check-static-${name}" { nativeBuildInputs = [ pkgs.binutils-unwrapped ]; } ''
+              binary=${package}/bin/rust-cpp-app
+              if readelf --program-headers "$binary" | grep -q INTERP; then
+                echo "$binary requests a program interpreter" >&2
+                exit 1
+              fi
+              if readelf --dynamic "$binary" | grep -q NEEDED; then
+                echo "$binary depends on shared libraries:" >&2
+                readelf --dynamic "$binary" | grep NEEDED >&2
+                exit 1
+              fi
+              touch "$out"
+            '';
+
+          x86_64-linux = mkPackage builders.x86_64-linux;
+          aarch64-linux = mkPackage builders.aarch64-linux;
+        in
+        {
+          packages = {
+            default = mkPackage builders.local;
+            inherit x86_64-linux aarch64-linux;
+          };
+
+          checks = {
+            static-x86_64-linux = mkStaticCheck "x86_64-linux" x86_64-linux;
+            static-aarch64-linux = mkStaticCheck "aarch64-linux" aarch64-linux;
+
+            tests = builders.local.callPackage lib.mkRustPackage {
+              inherit src depsSrc;
+              cargoToml = ./Cargo.toml;
+              mode = "test";
+            };
+          };
+        };
+    };
+}
@@ -0,0 +1,30 @@
+use std::ffi::{c_char, CStr, CString};
+
+extern "C" {
+    fn greeting(name: *const c_char, buf: *mut c_char, len: usize) -> usize;
+}
+
+fn greet(name: &str) -> String {
+    let name = CString::new(name).unwrap();
+    let mut buf = vec![0 as c_char; 128];
+    // SAFETY: `name` is NUL-terminated and `buf` is valid for `buf.len()` bytes
+    unsafe { greeting(name.as_ptr(), buf.as_mut_ptr(), buf.len()) };
+    // SAFETY: greeting always NUL-terminates the buffer
+    unsafe { CStr::from_ptr(buf.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn main() {
+    println!("{}", greet("Rust"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_greet() {
+        assert_eq!(greet("Nix"), "Hello from C++, Nix!");
+    }
+}
@@ -104,6 +104,306 @@
               );
             allCiToolsInShell = shell: builtins.all (toolName: hasToolInShell shell toolName) ciToolNames;
             noCiToolsInShell = shell: builtins.all (toolName: !(hasToolInShell shell toolName)) ciToolNames;
+            # Source trees of a crate with .proto files, with and without the preset
+            protoSrc = lib.mkSrc {
+              root = ./tests/fixtures/proto-src;
+              fs = pkgs.lib.fileset;
+              presets = [ "proto" ];
+            };
+            protoSrcWithoutPreset = lib.mkSrc {
+              root = ./tests/fixtures/proto-src;
+              fs = pkgs.lib.fileset;
+            };
+            # Source tree of a crate with sqlx metadata and migrations
+            sqlxSrc = lib.mkSrc {
+              root = ./tests/fixtures/sqlx-src;
+              fs = pkgs.lib.fileset;
+              presets = [ "sqlx" ];
+            };
+
+            # Workspace with a binary and a library member
+            workspace = lib.mkRustWorkspace {
+              builder = lib.mkRustBuilder { };
+              src = lib.mkSrc {
+                root = ./tests/fixtures/workspace;
+                fs = pkgs.lib.fileset;
+              };
+              depsSrc = lib.mkDepsSrc {
+                root = ./tests/fixtures/workspace;
+                fs = pkgs.lib.fileset;
+              };
+              cargoToml = ./tests/fixtures/workspace/Cargo.toml;
+            };
+            # Workspace member with its own native libraries, which the shared
+            # dependency build would not get
+            workspaceMemberNativeLibs = lib.mkRustWorkspace {
+              builder = lib.mkRustBuilder { };
+              src = lib.mkSrc {
+                root = ./tests/fixtures/workspace;
+                fs = pkgs.lib.fileset;
+              };
+              depsSrc = lib.mkDepsSrc {
+                root = ./tests/fixtures/workspace;
+                fs = pkgs.lib.fileset;
+              };
+              cargoToml = ./tests/fixtures/workspace/Cargo.toml;
+              memberArgs.workspace-core.nativeLibs = [ "sqlite" ];
+            };
+
+            # Library member of the workspace, tested and documented on its own
+            libraryArgs = {
+              src = lib.mkSrc {
+                root = ./tests/fixtures/workspace;
+                fs = pkgs.lib.fileset;
+              };
+              depsSrc = lib.mkDepsSrc {
+                root = ./tests/fixtures/workspace;
+                fs = pkgs.lib.fileset;
+              };
+              cargoToml = ./tests/fixtures/workspace/crates/core/Cargo.toml;
+            };
+            libraryTest = (lib.mkRustBuilder { }).callPackage lib.mkRustLibrary (
+              libraryArgs // { mode = "test"; }
+            );
+            libraryDoc = (lib.mkRustBuilder { }).callPackage lib.mkRustLibrary (
+              libraryArgs // { mode = "doc"; }
+            );
+            libraryNextest = (lib.mkRustBuilder { }).callPackage lib.mkRustLibrary (
+              libraryArgs // { mode = "nextest"; }
+            );
+
+            workspaceChecksArgs = {
+              builder = lib.mkRustBuilder { };
+              src = lib.mkSrc {
+                root = ./tests/fixtures/workspace;
+                fs = pkgs.lib.fileset;
+              };
+              depsSrc = lib.mkDepsSrc {
+                root = ./tests/fixtures/workspace;
+                fs = pkgs.lib.fileset;
+              };
+              cargoToml = ./tests/fixtures/workspace/Cargo.toml;
+            };
+            workspaceChecks = lib.mkWorkspaceChecks workspaceChecksArgs;
+            # Workspace checks with a mode in args, which the checks set themselves
+            workspaceChecksWithMode = lib.mkWorkspaceChecks (
+              workspaceChecksArgs // { args.mode = "test"; }
+            );
+
+            targetBuilders = lib.mkRustBuilders {
+              targets = [
+                { name = "local"; }
+                {
+                  name = "aarch64-linux";
+                  crossSystem = "aarch64-unknown-linux-musl";
+                  isStatic = true;
+                }
+              ];
+            };
+            wasmWithLinker =
+              (lib.mkRustBuilders {
+                targets = [
+                  {
+                    name = "wasm32-wasip1";
+                    crossSystem = "wasm32-wasip1";
+                    linker = "mold";
+                  }
+                ];
+              }).wasm32-wasip1;
+            # Two targets under the same name, only one of them could be returned
+            duplicateBuilders = lib.mkRustBuilders {
+              targets = [
+                { name = "local"; }
+                {
+                  name = "local";
+                  channel = "nightly";
+                }
+              ];
+            };
+            # A default linker for all builders, which not every target can use
+            moldBuilders = lib.mkRustBuilders { linker = "mold"; };
+
+            # Package-level rustflags have to be appended to the builder's flags
+            rustflagsCrate = (lib.mkRustBuilder { rustflags = [ "--cfg=builder_flag" ]; }).callPackage (
+              { stdenv, ... }:
+              stdenv.mkDerivation {
+                name = "rustflags-crate";
+                cargoArtifacts = null;
+                CARGO_BUILD_RUSTFLAGS = "--cfg=package_flag";
+              }
+            ) { };
+            # Package rustflags without builder.callPackage, which would drop them
+            rustflagsWithoutBuilder =
+              let
+                builder = lib.mkRustBuilder { };
+              in
+              builder.pkgs.callPackage lib.mkRustPackage (
+                modeArgs
+                // {
+                  inherit (builder) craneLib;
+                  rustflags = [ "--cfg=package_flag" ];
+                }
+              );
+
+            # Package built with a static cross builder, requesting nativeLibs
+            crossStaticCrate = targetBuilders.aarch64-linux.callPackage lib.mkRustPackage {
+              src = ./examples/rust-app;
+              depsSrc = ./examples/rust-app;
+              cargoToml = ./examples/rust-app/Cargo.toml;
+              nativeLibs = [ "sqlite" ];
+            };
+
+            # Package running bindgen, built with a static cross builder
+            bindgenCrate = targetBuilders.aarch64-linux.callPackage lib.mkRustPackage (
+              modeArgs
+              // {
+                withBindgen = true;
+              }
+            );
+            hasBindgenHook = builtins.any (input: (input.name or "") == "rust-bindgen-hook");
+
+            # Package with C++ code built with a static cross builder
+            cppStaticCrate = targetBuilders.aarch64-linux.callPackage lib.mkRustPackage {
+              src = ./examples/rust-cpp-app;
+              depsSrc = ./examples/rust-cpp-app;
+              cargoToml = ./examples/rust-cpp-app/Cargo.toml;
+            };
+
+            # Package arguments selecting the test mode through `mode`, and
+            # through two legacy flags selecting different modes
+            modeArgs = {
+              src = ./examples/rust-app;
+              depsSrc = ./examples/rust-app;
+              cargoToml = ./examples/rust-app/Cargo.toml;
+            };
+            testModeCrate = targetBuilders.local.callPackage lib.mkRustPackage (
+              modeArgs
+              // {
+                mode = "test";
+                runTests = true;
+              }
+            );
+            # Test checks per toolchain, and a clippy matrix selected through args
+            toolchainChecks = lib.mkToolchainChecks {
+              args = modeArgs;
+              toolchains = [
+                "stable"
+                "msrv"
+              ];
+            };
+            clippyToolchainChecks = lib.mkToolchainChecks {
+              args = modeArgs // {
+                mode = "clippy";
+              };
+              toolchains = [ "stable" ];
+              namePrefix = "clippy";
+            };
+            conflictingModesCrate = targetBuilders.local.callPackage lib.mkRustPackage (
+              modeArgs
+              // {
+                runTests = true;
+                runClippy = true;
+              }
+            );
+
+            # Structured feature arguments, for a build and a feature combination check
+            featuresArgs = {
+              src = ./tests/fixtures/features;
+              depsSrc = ./tests/fixtures/features;
+              cargoToml = ./tests/fixtures/features/Cargo.toml;
+            };
+            featuresCrate = targetBuilders.local.callPackage lib.mkRustLibrary (
+              featuresArgs
+              // {
+                features = [ "tls" ];
+                noDefaultFeatures = true;
+              }
+            );
+            featurePowersetCrate = targetBuilders.local.callPackage lib.mkRustPackage (
+              featuresArgs // { mode = "features"; }
+            );
+            testShards = lib.mkTestShards {
+              builder = targetBuilders.local;
+              args = modeArgs // {
+                nextestRetries = 2;
+                quarantinedTests = [ "tests::flaky" ];
+              };
+              shards = 3;
+            };
+            servicesCrate = targetBuilders.local.callPackage lib.mkRustPackage (
+              modeArgs
+              // {
+                mode = "test";
+                testServices = {
+                  postgres.database = "app";
+                  redis = { };
+                };
+              }
+            );
+            sqlxCrate = targetBuilders.local.callPackage lib.mkRustPackage (
+              modeArgs
+              // {
+                mode = "sqlx";
+              }
+            );
+            nextestCrate = targetBuilders.local.callPackage lib.mkRustPackage (
+              modeArgs
+              // {
+                mode = "nextest";
+                nextestProfile = "ci";
+              }
+            );
+
+            # Default builder set, for the checks of the built-in targets
+            allBuilders = lib.mkRustBuilders { };
+            armv7Crate = allBuilders.armv7l-linux.callPackage lib.mkRustPackage modeArgs;
+            windowsTestCrate = allBuilders.x86_64-windows.callPackage lib.mkRustPackage (
+              modeArgs // { mode = "test"; }
+            );
+            windowsCrate = allBuilders.x86_64-windows.callPackage lib.mkRustPackage modeArgs;
+            wasiCrate = allBuilders.wasm32-wasip1.callPackage lib.mkRustPackage modeArgs;
+            wasmCrate = allBuilders.wasm32-unknown.callPackage lib.mkRustPackage modeArgs;
+            crossTestCrate = targetBuilders.aarch64-linux.callPackage lib.mkRustPackage (
+              modeArgs // { mode = "test"; }
+            );
+            glibcCrate = allBuilders.aarch64-linux-gnu.callPackage lib.mkRustPackage modeArgs;
+            glibcX86Crate = allBuilders.x86_64-linux-gnu.callPackage lib.mkRustPackage modeArgs;
+            # zig should only link the target's artifacts. For the build
+            # platform's own triple it comes with the Rust flags, which don't
+            # apply to build scripts and proc-macros.
+            linksWithZig =
+              crate: triple:
+              let
+                linkerVar = "CARGO_TARGET_${
+                  pkgs.lib.toUpper (builtins.replaceStrings [ "-" ] [ "_" ] triple)
+                }_LINKER";
+              in
+              if triple == pkgs.stdenv.hostPlatform.config then
+                !(crate ? ${linkerVar})
+                && pkgs.lib.hasInfix "-C linker=" crate.CARGO_BUILD_RUSTFLAGS
+                && pkgs.lib.hasInfix "zigcc-${triple}" crate.CARGO_BUILD_RUSTFLAGS
+              else
+                pkgs.lib.hasInfix "zigcc-${triple}" crate.${linkerVar}
+                && !(pkgs.lib.hasInfix "zigcc-" crate.CARGO_BUILD_RUSTFLAGS);
+            glibcOnMusl =
+              (lib.mkRustBuilders {
+                targets = [
+                  {
+                    name = "aarch64-linux";
+                    crossSystem = "aarch64-unknown-linux-musl";
+                    glibcVersion = "2.28";
+                  }
+                ];
+              }).aarch64-linux;
+            pinnedBuilder = lib.mkRustBuilder { rustVersion = "1.83.0"; };
+            pinnedNightlyVersion = lib.mkRustBuilder {
+              channel = "nightly";
+              rustVersion = "1.83.0";
+            };
+            pinnedShell = lib.mkDevShell {
+              rustVersion = "1.83.0";
+              includeCiPackages = false;
+            };
           in
           {
             # Import nixpkgs with overlays
@@ -167,6 +467,7 @@
                   ".direnv/**"
                   # Template files (contain Nix substitution variables)
                   "lib/setup-hook-darwin.sh"
+                  "lib/setup-hook-bindgen.sh"
                 ];
                 formatter = {
                   shfmt = {
@@ -214,6 +515,385 @@
                 pkgs.runCommand "check-dev-shell-ci-packages-disabled" { } ''
                   touch "$out"
                 '';
+
+              # mkRustBuilders with targets should return exactly the requested builders
+              rustBuildersTargets =
+                assert pkgs.lib.assertMsg (
+                  builtins.attrNames targetBuilders == [
+                    "aarch64-linux"
+                    "local"
+                  ]
+                ) "mkRustBuilders with targets is expected to return only the listed builders";
+                assert pkgs.lib.assertMsg (
+                  !(builtins.tryEval duplicateBuilders).success
+                ) "mkRustBuilders is expected to reject duplicate target names";
+                pkgs.runCommand "check-rust-builders-targets" { } ''
+                  touch "$out"
+                '';
+
+              # Builders should expose their target metadata
+              rustBuilderMetadata =
+                let
+                  builder = targetBuilders.aarch64-linux;
+                in
+                assert pkgs.lib.assertMsg (
+                  builder.rustTarget == "aarch64-unknown-linux-musl"
+                  && builder.isCross
+                  && builder.isStatic
+                  && builder.pkgs.stdenv.hostPlatform.isAarch64
+                  && !targetBuilders.local.isCross
+                ) "builders are expected to expose their target triple, isCross, isStatic and pkgs";
+                pkgs.runCommand "check-rust-builder-metadata" { } ''
+                  touch "$out"
+                '';
+
+              # The armv7, riscv64 and i686 builders should be static musl cross builders
+              rustBuildersMuslStatic =
+                assert pkgs.lib.assertMsg (
+                  allBuilders.armv7l-linux.rustTarget == "armv7-unknown-linux-musleabihf"
+                  && allBuilders.riscv64-linux.rustTarget == "riscv64gc-unknown-linux-musl"
+                  && allBuilders.i686-linux.rustTarget == "i686-unknown-linux-musl"
+                  && builtins.all (name: allBuilders.${name}.isStatic) [
+                    "armv7l-linux"
+                    "riscv64-linux"
+                    "i686-linux"
+                  ]
+                ) "the armv7, riscv64 and i686 builders are expected to target static musl";
+                assert pkgs.lib.assertMsg (
+                  armv7Crate ? ARMV7_UNKNOWN_LINUX_MUSLEABIHF_OPENSSL_LIB_DIR
+                ) "the armv7 builder is expected to point openssl-sys at the target openssl";
+                pkgs.runCommand "check-rust-builders-musl-static" { } ''
+                  touch "$out"
+                '';
+
+              # The Windows builder should install runtime DLLs and run tests under Wine
+              rustBuilderWindows =
+                assert pkgs.lib.assertMsg (
+                  allBuilders.x86_64-windows.rustTarget == "x86_64-pc-windows-gnu"
+                  && pkgs.lib.hasInfix "-name '*.dll'" windowsCrate.preFixup
+                ) "the Windows builder is expected to install the runtime DLLs next to the .exe files";
+                assert pkgs.lib.assertMsg (
+                  !(pkgs.stdenv.hostPlatform.isLinux && pkgs.stdenv.hostPlatform.isx86_64)
+                  || pkgs.lib.hasInfix "wine-runner" windowsTestCrate.CARGO_TARGET_X86_64_PC_WINDOWS_GNU_RUNNER
+                ) "Windows tests are expected to run under Wine on x86_64 Linux";
+                pkgs.runCommand "check-rust-builder-windows" { } ''
+                  touch "$out"
+                '';
+
+              # wasm builders should skip the native toolchain and run WASI tests with wasmtime
+              rustBuilderWasm =
+                assert pkgs.lib.assertMsg (
+                  allBuilders.wasm32-wasip1.isWasm
+                  && allBuilders.wasm32-unknown.rustTarget == "wasm32-unknown-unknown"
+                  && !(wasiCrate ? CARGO_TARGET_WASM32_WASIP1_LINKER)
+                  && !(wasiCrate ? OPENSSL_NO_PKG_CONFIG)
+                  && pkgs.lib.hasInfix "-name '*.wasm'" wasiCrate.installPhase
+                ) "wasm builders are expected to skip the C linker and openssl settings and install .wasm files";
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "wasmtime run" wasiCrate.CARGO_TARGET_WASM32_WASIP1_RUNNER
+                  && !(wasmCrate ? CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER)
+                ) "WASI builders are expected to run tests with wasmtime";
+                pkgs.runCommand "check-rust-builder-wasm" { } ''
+                  touch "$out"
+                '';
+
+              # Cross Linux builders should run the test binaries under QEMU
+              rustBuilderQemuRunner =
+                assert pkgs.lib.assertMsg (
+                  pkgs.stdenv.hostPlatform.isAarch64
+                  || pkgs.lib.hasSuffix "qemu-aarch64" crossTestCrate.CARGO_TARGET_AARCH64_UNKNOWN_LINUX_MUSL_RUNNER
+                ) "cross Linux builders are expected to run tests through qemu-aarch64";
+                pkgs.runCommand "check-rust-builder-qemu-runner" { } ''
+                  touch "$out"
+                '';
+
+              # glibc builders should link against the pinned glibc and reject newer symbols
+              rustBuilderGlibc =
+                assert pkgs.lib.assertMsg (
+                  allBuilders.aarch64-linux-gnu.glibcVersion == "2.28"
+                  && linksWithZig glibcCrate "aarch64-unknown-linux-gnu"
+                  && linksWithZig glibcX86Crate "x86_64-unknown-linux-gnu"
+                  && pkgs.lib.hasInfix "but the builder targets glibc 2.28" glibcCrate.preFixup
+                ) "glibc builders are expected to link with zig and check the required glibc version";
+                assert pkgs.lib.assertMsg (
+                  !(builtins.tryEval glibcOnMusl.callPackage).success
+                ) "glibcVersion is expected to be rejected for musl targets";
+                pkgs.runCommand "check-rust-builder-glibc" { } ''
+                  touch "$out"
+                '';
+
+              # rustVersion should pin the toolchain, nightlies only through nightlyDate
+              rustBuilderPinnedToolchain =
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "1.83.0" pinnedBuilder.toolchain.name
+                  && builtins.any (path: pkgs.lib.hasInfix "1.83.0" path) (
+                    pinnedShell.inputDerivation.nativeBuildInputs or [ ]
+                  )
+                ) "rustVersion is expected to select that stable toolchain for builders and dev shells";
+                assert pkgs.lib.assertMsg (
+                  !(builtins.tryEval pinnedNightlyVersion.toolchain.name).success
+                ) "a nightly toolchain pinned through rustVersion is expected to be rejected";
+                pkgs.runCommand "check-rust-builder-pinned-toolchain" { } ''
+                  touch "$out"
+                '';
+
+              # Selecting a linker the target cannot use should fail evaluation
+              rustBuilderLinkerValidation =
+                assert pkgs.lib.assertMsg (
+                  !(builtins.tryEval wasmWithLinker.callPackage).success
+                ) "a wasm builder with linker = \"mold\" is expected to be rejected";
+                assert pkgs.lib.assertMsg (
+                  moldBuilders.local.linker == (if pkgs.stdenv.hostPlatform.isLinux then "mold" else "lld")
+                  && moldBuilders.x86_64-windows.linker == null
+                  && moldBuilders.aarch64-darwin.linker != "mold"
+                  && moldBuilders.wasm32-wasip1.linker == null
+                  && moldBuilders.aarch64-linux-gnu.linker == null
+                ) "the mkRustBuilders linker is expected to apply only to the builders that support it";
+                pkgs.runCommand "check-rust-builder-linker-validation" { } ''
+                  touch "$out"
+                '';
+
+              # nativeLibs should be found through pkg-config scoped to the target
+              rustPackageNativeLibs =
+                assert pkgs.lib.assertMsg (
+                  crossStaticCrate ? PKG_CONFIG_PATH_aarch64_unknown_linux_musl
+                  && crossStaticCrate.SQLITE3_STATIC == "1"
+                ) "nativeLibs are expected to set target-scoped pkg-config and static variables";
+                pkgs.runCommand "check-rust-package-native-libs" { } ''
+                  touch "$out"
+                '';
+
+              # Cross builders should point cc-rs and cmake at the target toolchain
+              rustBuilderCrossCcEnv =
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasSuffix "aarch64-unknown-linux-musl-cc" crossStaticCrate.CC_aarch64_unknown_linux_musl
+                  && crossStaticCrate ? RANLIB_aarch64_unknown_linux_musl
+                  && crossStaticCrate ? CMAKE_TOOLCHAIN_FILE_aarch64_unknown_linux_musl
+                  && crossStaticCrate.CFLAGS_aarch64_unknown_linux_musl == "-fPIC"
+                ) "cross builders are expected to set the cc-rs and cmake target variables";
+                pkgs.runCommand "check-rust-builder-cross-cc-env" { } ''
+                  touch "$out"
+                '';
+
+              # Static builders should link the C++ runtime of cc-rs code statically
+              rustBuilderStaticCxx =
+                assert pkgs.lib.assertMsg (
+                  targetBuilders.aarch64-linux.cxxStdlib == "stdc++"
+                  && cppStaticCrate.CXXSTDLIB_aarch64_unknown_linux_musl == "static:-bundle=stdc++"
+                  && pkgs.lib.hasSuffix "aarch64-unknown-linux-musl-c++" cppStaticCrate.CXX_aarch64_unknown_linux_musl
+                  && targetBuilders.local.cxxStdlib == null
+                ) "static builders are expected to link the C++ runtime statically through CXXSTDLIB";
+                pkgs.runCommand "check-rust-builder-static-cxx" { } ''
+                  touch "$out"
+                '';
+
+              # withBindgen should add the libclang setup hook, and only when requested
+              rustPackageBindgen =
+                assert pkgs.lib.assertMsg (
+                  hasBindgenHook bindgenCrate.nativeBuildInputs
+                  && !(hasBindgenHook crossStaticCrate.nativeBuildInputs)
+                ) "withBindgen is expected to add the bindgen setup hook to the package";
+                pkgs.runCommand "check-rust-package-bindgen" { } ''
+                  touch "$out"
+                '';
+
+              # The proto preset should add .proto files to the source tree
+              mkSrcProtoPreset = pkgs.runCommand "check-mk-src-proto-preset" { } ''
+                test -f ${protoSrc}/proto/greeter.proto
+                test -f ${protoSrc}/src/lib.rs
+                test ! -e ${protoSrcWithoutPreset}/proto/greeter.proto
+                touch "$out"
+              '';
+
+              # Every workspace member should get a derivation, all sharing one
+              # dependency build
+              rustWorkspaceMembers =
+                assert pkgs.lib.assertMsg (
+                  builtins.attrNames workspace == [
+                    "workspace-app"
+                    "workspace-core"
+                  ]
+                ) "mkRustWorkspace is expected to return one derivation per member";
+                assert pkgs.lib.assertMsg (
+                  workspace.workspace-app.cargoArtifacts.drvPath == workspace.workspace-core.cargoArtifacts.drvPath
+                ) "workspace members are expected to share their dependency artifacts";
+                assert pkgs.lib.assertMsg (
+                  !(builtins.tryEval workspaceMemberNativeLibs.workspace-core.drvPath).success
+                ) "mkRustWorkspace is expected to reject dependency arguments in memberArgs";
+                pkgs.runCommand "check-rust-workspace-members" { } ''
+                  touch "$out"
+                '';
+
+              # Workspace checks should run clippy, tests and docs per member
+              rustWorkspaceChecks =
+                assert pkgs.lib.assertMsg (
+                  builtins.attrNames workspaceChecks == [
+                    "clippy-workspace-app"
+                    "clippy-workspace-core"
+                    "doc-workspace-app"
+                    "doc-workspace-core"
+                    "test-workspace-app"
+                    "test-workspace-core"
+                  ]
+                ) "mkWorkspaceChecks is expected to return clippy, test and doc checks per member";
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "-p workspace-core" workspaceChecks.test-workspace-core.buildPhase
+                  && !(pkgs.lib.hasInfix "--workspace" workspaceChecks.test-workspace-core.buildPhase)
+                  && pkgs.lib.hasInfix "-p workspace-core --no-deps" workspaceChecks.doc-workspace-core.buildPhase
+                ) "workspace checks are expected to be restricted to their member";
+                assert pkgs.lib.assertMsg (
+                  !(builtins.tryEval workspaceChecksWithMode).success
+                ) "mkWorkspaceChecks is expected to reject a mode in args";
+                pkgs.runCommand "check-rust-workspace-checks" { } ''
+                  touch "$out"
+                '';
+
+              # Feature arguments should apply to the dependency and the main build
+              rustPackageFeatures =
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "--no-default-features --features tls" featuresCrate.buildPhase
+                  && pkgs.lib.hasInfix "--no-default-features --features tls" featuresCrate.cargoArtifacts.buildPhase
+                ) "features are expected in the cargo arguments of the package and its dependencies";
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "cargo hack check --locked --feature-powerset" featurePowersetCrate.buildPhase
+                  && pkgs.lib.hasInfix "--all-features" featurePowersetCrate.cargoArtifacts.buildPhase
+                ) "the features mode is expected to run cargo-hack on top of all-features dependencies";
+                pkgs.runCommand "check-rust-package-features" { } ''
+                  touch "$out"
+                '';
+
+              # The nextest mode should keep the JUnit report of the selected profile
+              rustPackageNextest =
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "cargo nextest run --no-fail-fast --profile ci" nextestCrate.buildPhase
+                  && pkgs.lib.hasInfix "nextest/ci/junit.xml\" junit.xml" nextestCrate.buildPhase
+                  && pkgs.lib.hasInfix "cp junit.xml $out/" nextestCrate.installPhase
+                ) "the nextest mode is expected to run the selected profile and install its JUnit report";
+                assert pkgs.lib.assertMsg (
+                  !(nextestCrate.cargoArtifacts ? nextestProfile)
+                ) "nextest settings are expected to stay out of the dependency build";
+                pkgs.runCommand "check-rust-package-nextest" { } ''
+                  touch "$out"
+                '';
+
+              # Every shard should run its own partition of one shared archive
+              rustPackageTestShards =
+                assert pkgs.lib.assertMsg (
+                  builtins.attrNames testShards == [
+                    "test-shard-1"
+                    "test-shard-2"
+                    "test-shard-3"
+                  ]
+                ) "mkTestShards is expected to return one check per shard";
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "--partition count:2/3" testShards.test-shard-2.buildPhase
+                  && testShards.test-shard-2.cargoArtifacts == null
+                  && builtins.length (
+                    pkgs.lib.unique (map (shard: shard.buildPhase) (builtins.attrValues testShards))
+                  ) == 3
+                ) "test shards are expected to run their partition from the archive without compiling";
+                pkgs.runCommand "check-rust-package-test-shards" { } ''
+                  touch "$out"
+                '';
+
+              # Flaky tests should be retried, quarantined tests run on their own
+              rustPackageQuarantine =
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "--retries 2 --no-tests=pass -E 'not (test(=tests::flaky))'" testShards.test-shard-1.buildPhase
+                  && pkgs.lib.hasInfix "-E 'test(=tests::flaky)'" testShards.test-shard-1.buildPhase
+                ) "nextest runs are expected to retry failures and run quarantined tests separately";
+                pkgs.runCommand "check-rust-package-quarantine" { } ''
+                  touch "$out"
+                '';
+
+              # Test services should be started before and stopped after the tests
+              rustPackageTestServices =
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "export DATABASE_URL=\"postgresql://postgres@localhost/app?host=$PGHOST\"" servicesCrate.preBuild
+                  && pkgs.lib.hasInfix "export REDIS_URL=" servicesCrate.preBuild
+                  && pkgs.lib.hasInfix "pg_ctl -D \"$PGDATA\" -m fast -w stop" servicesCrate.preInstall
+                ) "test services are expected to be started in preBuild and stopped in preInstall";
+                pkgs.runCommand "check-rust-package-test-services" { } ''
+                  touch "$out"
+                '';
+
+              # The sqlx preset should add query metadata and migrations
+              mkSrcSqlxPreset = pkgs.runCommand "check-mk-src-sqlx-preset" { } ''
+                test -f ${sqlxSrc}/.sqlx/query-5f1d8a1e3c0b4c9a8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f7a6b5c.json
+                test -f ${sqlxSrc}/migrations/20260101000000_greetings.sql
+                test -f ${sqlxSrc}/src/lib.rs
+                touch "$out"
+              '';
+
+              # The sqlx mode should check the metadata against a migrated database,
+              # on top of the regular dependency artifacts
+              rustPackageSqlx =
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "cargo sqlx prepare --check" sqlxCrate.buildPhase
+                  && pkgs.lib.hasInfix "sqlx migrate run --source migrations" sqlxCrate.buildPhase
+                  && pkgs.lib.hasInfix "export DATABASE_URL=" sqlxCrate.preBuild
+                  && sqlxCrate.cargoArtifacts != null
+                ) "the sqlx mode is expected to run cargo sqlx prepare --check against PostgreSQL";
+                pkgs.runCommand "check-rust-package-sqlx" { } ''
+                  touch "$out"
+                '';
+
+              # Toolchain checks should run the tests once per requested toolchain
+              rustToolchainChecks =
+                assert pkgs.lib.assertMsg (
+                  builtins.attrNames toolchainChecks == [
+                    "test-msrv"
+                    "test-stable"
+                  ]
+                ) "mkToolchainChecks is expected to return one check per toolchain";
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "cargoWithProfile test" toolchainChecks.test-stable.buildPhase
+                  && builtins.attrNames clippyToolchainChecks == [ "clippy-stable" ]
+                  && pkgs.lib.hasInfix "cargoWithProfile clippy" clippyToolchainChecks.clippy-stable.buildPhase
+                ) "mkToolchainChecks is expected to run the tests unless args select another mode";
+                pkgs.runCommand "check-rust-toolchain-checks" { } ''
+                  touch "$out"
+                '';
+
+              # mkRustLibrary should test only its crate and support the doc mode
+              rustLibraryModes =
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "-p workspace-core --lib" libraryTest.buildPhase
+                  && !(pkgs.lib.hasInfix "--workspace" libraryTest.buildPhase)
+                ) "mkRustLibrary tests are expected to be restricted to the library crate";
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasInfix "-p workspace-core --lib" libraryDoc.buildPhase
+                  && !(pkgs.lib.hasInfix "libworkspace_core" libraryDoc.installPhase)
+                ) "mkRustLibrary is expected to build and install the crate documentation in doc mode";
+                assert pkgs.lib.assertMsg (
+                  !(builtins.tryEval libraryNextest.drvPath).success
+                ) "mkRustLibrary is expected to reject the report-only modes of mkRustPackage";
+                pkgs.runCommand "check-rust-library-modes" { } ''
+                  touch "$out"
+                '';
+
+              # Conflicting build mode flags should fail evaluation
+              rustPackageModeValidation =
+                assert pkgs.lib.assertMsg (
+                  (builtins.tryEval testModeCrate.name).success
+                  && !(builtins.tryEval conflictingModesCrate.name).success
+                ) "mkRustPackage is expected to reject runTests together with runClippy";
+                pkgs.runCommand "check-rust-package-mode-validation" { } ''
+                  touch "$out"
+                '';
+
+              # Builder and package rustflags should be merged, not replaced
+              rustBuilderRustflagsMerge =
+                assert pkgs.lib.assertMsg (
+                  pkgs.lib.hasSuffix "--cfg=builder_flag --cfg=package_flag" rustflagsCrate.CARGO_BUILD_RUSTFLAGS
+                  && rustflagsCrate.packageRustflags == "--cfg=package_flag"
+                ) "package rustflags are expected to be appended to the builder rustflags";
+                assert pkgs.lib.assertMsg (
+                  !(builtins.tryEval rustflagsWithoutBuilder.drvPath).success
+                ) "package rustflags are expected to be rejected without builder.callPackage";
+                pkgs.runCommand "check-rust-builder-rustflags-merge" { } ''
+                  touch "$out"
+                '';
             };
           };
 
@@ -42,22 +42,35 @@ rec {
   # Arguments:
   #   rustToolchain: Optional Rust toolchain derivation
   #   rustToolchainFile: Optional path to rust-toolchain.toml
+  #   channel: Toolchain channel: stable, beta or nightly (default: "stable")
+  #   rustVersion: Optional pinned stable version (e.g. "1.83.0") or beta date
+  #   nightlyDate: Optional pinned nightly date (e.g. "2026-09-01")
   #   cargoAudit: Optional cargo-audit package (defaults to unstable, since the new advisory DB entries require at least version 0.22)
   mkAuditApp =
     {
       rustToolchain ? null,
       rustToolchainFile ? null,
+      channel ? "stable",
+      rustVersion ? null,
+      nightlyDate ? null,
       cargoAudit ? pkgsUnstable.cargo-audit,
     }:
     let
-      # Use provided Rust toolchain or default from rust-toolchain.toml or stable Rust
+      rustToolchainSpec = import ./rust-toolchain.nix {
+        inherit
+          rustToolchainFile
+          channel
+          rustVersion
+          nightlyDate
+          ;
+      };
+
+      # Use provided Rust toolchain or resolve it like the builders do
       selectedRust =
         if rustToolchain != null then
           rustToolchain
-        else if rustToolchainFile != null then
-          pkgsUnstable.pkgsBuildHost.rust-bin.fromRustupToolchainFile rustToolchainFile
         else
-          pkgsUnstable.rust-bin.stable.latest.default;
+          rustToolchainSpec.select pkgsUnstable.pkgsBuildHost.rust-bin { };
     in
     flake-utils.lib.mkApp {
       drv = pkgs.writeShellApplication {
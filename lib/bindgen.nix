@@ -0,0 +1,36 @@
+# bindgen.nix - Setup hook for crates running bindgen at build time
+#
+# Provides libclang (LIBCLANG_PATH) and the clang arguments bindgen needs to
+# find the C headers, as BINDGEN_EXTRA_CLANG_ARGS_<triple> for the target and,
+# when cross-compiling, for the build platform.
+#
+# This is a low-level building block used by rust-package.nix and
+# rust-library.nix.
+
+{
+  pkgs, # Package set of the builder (cross package set for cross builds)
+  makeSetupHook, # Nix setup hook creator
+  rustTarget ? null, # Rust target triple (set by the builder)
+  buildRustTarget ? null, # Rust triple of the build platform (set by the builder)
+}:
+let
+  # Without the triples (package not built through a builder) the unscoped
+  # variable is used for the target only
+  argsVar =
+    triple:
+    if triple == null then
+      "BINDGEN_EXTRA_CLANG_ARGS"
+    else
+      "BINDGEN_EXTRA_CLANG_ARGS_${builtins.replaceStrings [ "-" ] [ "_" ] triple}";
+in
+makeSetupHook {
+  name = "rust-bindgen-hook";
+  substitutions = {
+    libclang = pkgs.pkgsBuildHost.llvmPackages.libclang.lib;
+    clang = pkgs.pkgsBuildHost.llvmPackages.clang;
+    targetCc = pkgs.stdenv.cc;
+    buildCc = pkgs.pkgsBuildBuild.stdenv.cc;
+    targetVar = argsVar rustTarget;
+    buildVar = if rustTarget == null then argsVar null else argsVar buildRustTarget;
+  };
+} ./setup-hook-bindgen.sh
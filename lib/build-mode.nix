@@ -0,0 +1,40 @@
+# build-mode.nix - Build mode resolution
+#
+# Resolves the build mode of rust-package.nix and rust-library.nix from the
+# explicit `mode` argument or the legacy boolean flags (`runTests`,
+# `runClippy`, ...), failing evaluation when they conflict instead of silently
+# picking one of them.
+#
+# This is a low-level building block used internally by the library.
+
+{
+  lib, # Nixpkgs lib utilities
+  caller, # Name used in error messages (e.g. "mkRustPackage")
+  supportedModes, # Modes the caller implements
+  mode ? null, # Explicitly requested mode
+  flags ? { }, # Legacy flags passed by the caller, e.g. { runTests = true; }
+}:
+let
+  # Mode selected by each legacy flag
+  flagModes = {
+    runTests = "test";
+    runClippy = "clippy";
+    buildDocs = "doc";
+    runBench = "bench";
+    buildBench = "bench-compile";
+    runCoverage = "coverage";
+  };
+
+  enabledFlags = builtins.attrNames (lib.filterAttrs (_: enabled: enabled) flags);
+  flagMode = if enabledFlags == [ ] then "build" else flagModes.${builtins.head enabledFlags};
+  resolvedMode = if mode != null then mode else flagMode;
+
+  conflictingFlags = builtins.filter (flag: flagModes.${flag} != mode) enabledFlags;
+in
+assert lib.assertMsg (builtins.length enabledFlags <= 1)
+  "${caller}: ${lib.concatStringsSep ", " enabledFlags} select different build modes, set a single `mode` instead";
+assert lib.assertMsg (mode == null || conflictingFlags == [ ])
+  "${caller}: mode = \"${toString mode}\" conflicts with ${lib.concatStringsSep ", " conflictingFlags}";
+assert lib.assertMsg (builtins.elem resolvedMode supportedModes)
+  "${caller}: unsupported mode '${resolvedMode}', expected one of: ${lib.concatStringsSep ", " supportedModes}";
+resolvedMode
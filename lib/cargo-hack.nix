@@ -0,0 +1,38 @@
+# cargo-hack.nix - Cargo feature combination checker
+#
+# Creates a derivation that runs `cargo hack check` over combinations of the
+# crate's features (e.g. --feature-powerset or --each-feature), so feature
+# combinations that don't compile are caught.
+# The dependency artifacts are expected to be built with all features enabled,
+# so most dependencies are reused rather than rebuilt for every combination.
+
+{
+  mkCargoDerivation,
+  cargo-hack,
+}:
+
+{
+  cargoArtifacts,
+  cargoExtraArgs ? "",
+  cargoHackExtraArgs ? "--feature-powerset",
+
+  ...
+}@origArgs:
+let
+  # Remove cargo-hack specific arguments that aren't needed for the base derivation
+  args = builtins.removeAttrs origArgs [
+    "cargoExtraArgs"
+    "cargoHackExtraArgs"
+  ];
+in
+mkCargoDerivation (
+  args
+  // {
+    inherit cargoArtifacts;
+    pnameSuffix = "-features"; # Distinguish feature checks from regular builds
+
+    buildPhaseCargoCommand = "cargo hack check --locked ${cargoHackExtraArgs} ${cargoExtraArgs}";
+
+    nativeBuildInputs = (args.nativeBuildInputs or [ ]) ++ [ cargo-hack ];
+  }
+)
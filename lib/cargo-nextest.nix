@@ -0,0 +1,180 @@
+# cargo-nextest.nix - cargo-nextest test runner
+#
+# Creates a derivation that runs the tests with cargo-nextest and keeps the
+# results: the JUnit XML report (with per-test timings), the test list and the
+# nextest output are installed to $out.
+#
+# With `archiveOnly` the derivation only compiles the tests into a nextest
+# archive ($out/archive.tar.zst). Test runs given that archive through
+# `nextestArchive` don't compile anything and can run a single partition of
+# the tests, so several derivations share one test build.
+#
+# Failing tests are retried `nextestRetries` times. Quarantined tests run
+# separately after the other tests: their results are installed to $out, but
+# their failures don't fail the derivation. Either run may select no tests at
+# all, e.g. a partition containing only quarantined tests.
+#
+# Unlike the other Cargo derivations, the cargo artifacts are not installed;
+# the exit code of the test run is kept in $out/exit-code instead.
+
+{
+  lib,
+  mkCargoDerivation,
+  cargo-nextest,
+  writeText,
+  archiveOnly ? false,
+}:
+
+{
+  cargoArtifacts,
+  cargoExtraArgs ? "",
+  cargoTestExtraArgs ? "",
+  nextestProfile ? "default",
+  nextestConfigFile ? null,
+  nextestAllowFailures ? false,
+  nextestArchive ? null,
+  nextestPartition ? null,
+  nextestRetries ? 0,
+  quarantinedTests ? [ ],
+
+  ...
+}@origArgs:
+let
+  # Remove nextest-specific arguments that aren't needed for the base derivation
+  args = builtins.removeAttrs origArgs [
+    "cargoExtraArgs"
+    "cargoTestExtraArgs"
+    "nextestProfile"
+    "nextestConfigFile"
+    "nextestAllowFailures"
+    "nextestArchive"
+    "nextestPartition"
+    "nextestRetries"
+    "quarantinedTests"
+  ];
+
+  # Enables the JUnit report for the selected profile. Tool configuration has
+  # a lower precedence than the repository's .config/nextest.toml, so settings
+  # of the project's own profiles are kept.
+  junitConfig = writeText "nextest-junit.toml" ''
+    [profile.${nextestProfile}.junit]
+    path = "junit.xml"
+  '';
+
+  configArgs = [
+    "--profile ${nextestProfile}"
+    "--tool-config-file nix-lib:${junitConfig}"
+  ]
+  ++ (if nextestConfigFile != null then [ "--config-file ${nextestConfigFile}" ] else [ ]);
+
+  # The cargo arguments select what gets compiled, runs from an archive use the
+  # test binaries it contains instead
+  cargoArgs = [
+    cargoExtraArgs
+    cargoTestExtraArgs
+  ];
+  archiveArgs = [
+    "--archive-file ${nextestArchive}/archive.tar.zst"
+    "--workspace-remap ."
+  ];
+
+  nextestArgs = toString (configArgs ++ (if nextestArchive != null then archiveArgs else cargoArgs));
+  listArgs = toString (
+    [ nextestArgs ] ++ lib.optional (nextestPartition != null) "--partition ${nextestPartition}"
+  );
+  runArgs = toString (
+    [ listArgs ] ++ lib.optional (nextestRetries > 0) "--retries ${toString nextestRetries}"
+  );
+
+  # Quarantined tests are selected by their exact name, e.g. "tests::flaky_network"
+  hasQuarantine = quarantinedTests != [ ];
+  quarantineFilter = lib.concatMapStringsSep " | " (test: "test(=${test})") quarantinedTests;
+  junitFile = "\${CARGO_TARGET_DIR:-target}/nextest/${nextestProfile}/junit.xml";
+
+  archiveDerivation = mkCargoDerivation (
+    args
+    // {
+      inherit cargoArtifacts;
+      pnameSuffix = "-nextest-archive";
+
+      buildPhaseCargoCommand = ''
+        cargo nextest archive ${toString (configArgs ++ cargoArgs)} --archive-file archive.tar.zst
+      '';
+
+      doInstallCargoArtifacts = false;
+      installPhaseCommand = ''
+        mkdir -p $out
+        cp archive.tar.zst $out/
+      '';
+
+      nativeBuildInputs = (args.nativeBuildInputs or [ ]) ++ [ cargo-nextest ];
+    }
+  );
+
+  runDerivation = mkCargoDerivation (
+    args
+    // {
+      inherit cargoArtifacts;
+      pnameSuffix = "-nextest"; # Distinguish nextest runs from regular builds
+
+      buildPhaseCargoCommand = ''
+        cargo nextest list --message-format json ${listArgs} > test-list.json
+
+        set +e
+        cargo nextest run --no-fail-fast ${runArgs} ${
+          lib.optionalString hasQuarantine "--no-tests=pass -E ${lib.escapeShellArg "not (${quarantineFilter})"}"
+        } 2>&1 | tee nextest.log
+        nextestStatus=''${PIPESTATUS[0]}
+        set -e
+        if [ -f "${junitFile}" ]; then
+          mv "${junitFile}" junit.xml
+        fi
+      ''
+      + lib.optionalString hasQuarantine ''
+        set +e
+        cargo nextest run --no-fail-fast --no-tests=pass ${runArgs} \
+          -E ${lib.escapeShellArg quarantineFilter} 2>&1 | tee quarantine.log
+        quarantineStatus=''${PIPESTATUS[0]}
+        set -e
+        if [ -f "${junitFile}" ]; then
+          mv "${junitFile}" quarantine-junit.xml
+        fi
+        if [ "$quarantineStatus" != 0 ]; then
+          echo "quarantined tests failed with exit code $quarantineStatus, ignoring" >&2
+        fi
+      '';
+
+      # Keep the reports instead of the cargo artifacts
+      doInstallCargoArtifacts = false;
+      installPhaseCommand = ''
+        mkdir -p $out
+        cp test-list.json nextest.log $out/
+        if [ -f junit.xml ]; then
+          cp junit.xml $out/
+        fi
+        echo "$nextestStatus" > $out/exit-code
+      ''
+      + lib.optionalString hasQuarantine ''
+        cp quarantine.log $out/
+        if [ -f quarantine-junit.xml ]; then
+          cp quarantine-junit.xml $out/
+        fi
+        echo "$quarantineStatus" > $out/quarantine-exit-code
+      ''
+      + (
+        if nextestAllowFailures then
+          ""
+        else
+          ''
+            if [ "$nextestStatus" != 0 ]; then
+              echo "cargo nextest run failed with exit code $nextestStatus" >&2
+              exit "$nextestStatus"
+            fi
+          ''
+      );
+
+      nativeBuildInputs = (args.nativeBuildInputs or [ ]) ++ [ cargo-nextest ];
+    }
+  );
+in
+if archiveOnly then archiveDerivation else runDerivation
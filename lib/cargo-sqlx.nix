@@ -0,0 +1,47 @@
+# cargo-sqlx.nix - sqlx offline metadata verification
+#
+# Creates a derivation that applies the migrations of the source tree to a
+# database and runs `cargo sqlx prepare --check`, failing when the committed
+# .sqlx query metadata doesn't match the queries and migrations.
+# The database itself is provided by the caller (e.g. test-services.nix), which
+# exports DATABASE_URL.
+# sqlx checks the queries through `cargo check`, which reuses the regular
+# dependency artifacts of the crate.
+
+{
+  mkCargoDerivation,
+  sqlx-cli,
+}:
+
+{
+  cargoArtifacts,
+  cargoExtraArgs ? "",
+  sqlxMigrations ? "migrations",
+  sqlxPrepareExtraArgs ? "",
+
+  ...
+}@origArgs:
+let
+  # Remove sqlx-specific arguments that aren't needed for the base derivation
+  args = builtins.removeAttrs origArgs [
+    "cargoExtraArgs"
+    "sqlxMigrations"
+    "sqlxPrepareExtraArgs"
+  ];
+in
+mkCargoDerivation (
+  args
+  // {
+    inherit cargoArtifacts;
+    pnameSuffix = "-sqlx"; # Distinguish sqlx checks from regular builds
+
+    buildPhaseCargoCommand = ''
+      if [ -d ${sqlxMigrations} ]; then
+        sqlx migrate run --source ${sqlxMigrations}
+      fi
+      cargo sqlx prepare --check ${sqlxPrepareExtraArgs} -- ${cargoExtraArgs}
+    '';
+
+    nativeBuildInputs = (args.nativeBuildInputs or [ ]) ++ [ sqlx-cli ];
+  }
+)
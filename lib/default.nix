@@ -34,6 +34,17 @@ let
   };
 
   lib = pkgs.lib;
+
+  # Arguments selecting the build mode of mkRustPackage
+  modeArgNames = [
+    "mode"
+    "runTests"
+    "runClippy"
+    "buildDocs"
+    "runBench"
+    "buildBench"
+    "runCoverage"
+  ];
 in
 rec {
   ciTools = import ./ci-tools.nix;
@@ -53,11 +64,35 @@ rec {
   # Functions for creating Rust build environments with cross-compilation support
 
   # Create all Rust builders for different platforms
-  # Returns: { local, localNightly, x86_64-linux, aarch64-linux, x86_64-darwin, aarch64-darwin }
+  # Returns: { local, localNightly, localCoverage, x86_64-linux, aarch64-linux, x86_64-linux-gnu,
+  #            aarch64-linux-gnu, armv7l-linux, riscv64-linux, i686-linux, x86_64-windows,
+  #            wasm32-unknown, wasm32-wasip1, wasm32-wasip2, x86_64-darwin, aarch64-darwin }
+  #
+  # Pass `targets` to get exactly the listed builders instead, keyed by name:
+  #   targets = [
+  #     { name = "local"; }
+  #     { name = "aarch64-linux"; crossSystem = "aarch64-unknown-linux-musl"; isStatic = true; }
+  #   ];
+  # Each target accepts crossSystem, isStatic, channel, withLlvmTools, rustflags,
+  # extraEnv, glibcVersion, linker and cxxStdlib.
+  #
+  # `linker` ("mold", "lld", "bfd" or "wild") selects the linker backend for all
+  # builders that can use it, native and cross; the others keep their default.
+  # A linker set on a single target fails evaluation if the target can't use
+  # it. By default native builds use mold (lld on Darwin) and cross builds use
+  # the toolchain's default linker.
+  #
+  # The toolchain can be pinned with `rustVersion` (e.g. "1.83.0"), `channel`
+  # ("stable", "beta" or "nightly") and `nightlyDate` (used by nightly builders).
   mkRustBuilders =
     {
       localSystem ? system,
       rustToolchainFile ? null,
+      channel ? "stable",
+      rustVersion ? null,
+      nightlyDate ? null,
+      linker ? null,
+      targets ? null,
     }:
     let
       buildersLib = import ./rust-builders.nix {
@@ -68,10 +103,14 @@ rec {
           crane
           localSystem
           rustToolchainFile
+          channel
+          rustVersion
+          nightlyDate
+          linker
           ;
       };
     in
-    buildersLib.mkAllBuilders { };
+    if targets == null then buildersLib.mkAllBuilders { } else buildersLib.mkBuilders targets;
 
   # Create a single Rust builder for a specific platform
   # Useful when you only need one specific builder
@@ -83,7 +122,14 @@ rec {
       isStatic ? false,
       useRustNightly ? false,
       rustToolchainFile ? null,
+      channel ? "stable",
+      rustVersion ? null,
+      nightlyDate ? null,
       withLlvmTools ? false,
+      rustflags ? [ ],
+      extraEnv ? { },
+      linker ? null,
+      cxxStdlib ? "stdc++",
     }:
     import ./rust-builder.nix {
       inherit
@@ -97,10 +143,42 @@ rec {
         isStatic
         useRustNightly
         rustToolchainFile
+        channel
+        rustVersion
+        nightlyDate
         withLlvmTools
+        rustflags
+        extraEnv
+        linker
+        cxxStdlib
         ;
     };
 
+  # Resolve the Rust toolchain for the local platform
+  # Takes the same toolchain arguments as mkRustBuilders, useful for passing a
+  # matching toolchain to other tools.
+  mkRustToolchain =
+    {
+      rustToolchainFile ? null,
+      channel ? "stable",
+      rustVersion ? null,
+      nightlyDate ? null,
+      nightly ? false, # Select the nightly toolchain (pinned by nightlyDate)
+      targets ? [ ],
+      extensions ? [ ],
+    }:
+    let
+      rustToolchain = import ./rust-toolchain.nix {
+        inherit
+          rustToolchainFile
+          channel
+          rustVersion
+          nightlyDate
+          ;
+      };
+    in
+    rustToolchain.select pkgs.rust-bin { inherit nightly targets extensions; };
+
   # Rust Package Builder
   # -------------------
   # Low-level function for building Rust packages (binary crates)
@@ -116,6 +194,225 @@ rec {
 
   mkRustLibrary = import ./rust-library.nix;
 
+  # Rust Workspace
+  # --------------
+  # Build every member of a Cargo workspace with one builder
+
+  # Create one derivation per workspace member, keyed by crate name
+  # Binaries are built with mkRustPackage, libraries with mkRustLibrary. The
+  # dependencies of all members are built once and shared.
+  mkRustWorkspace =
+    {
+      builder, # Builder used for all members (e.g. builders.local)
+      src,
+      depsSrc,
+      cargoToml, # Workspace root Cargo.toml
+      name ? "workspace",
+      members ? null, # Member crate names to build (default: all members)
+      args ? { }, # Arguments for every member, accepted by mkRustPackage and mkRustLibrary
+      memberArgs ? { }, # Arguments per member, keyed by crate name (or a function of the name)
+      package ? null, # Package function for all members (default: by crate kind)
+    }:
+    import ./rust-workspace.nix {
+      inherit
+        lib
+        builder
+        src
+        depsSrc
+        cargoToml
+        name
+        members
+        args
+        memberArgs
+        package
+        ;
+    };
+
+  # Create clippy, test and doc checks for every workspace member
+  # Returns an attrset ready to merge into `checks`, e.g.
+  #   { clippy-my-core = <drv>; test-my-core = <drv>; doc-my-core = <drv>; ... }
+  #
+  # Each check only covers its own crate, so a failing crate doesn't mask the
+  # others and results are cached per crate. Clippy and test dependencies are
+  # built once and shared by all members (docs are built without artifacts,
+  # as with mode = "doc").
+  #
+  # The mode of each check is set by `checks`, so `args` and `memberArgs` may
+  # not select one. Their cargoTestExtraArgs are kept, only the default
+  # `--workspace` is dropped.
+  mkWorkspaceChecks =
+    {
+      builder,
+      src,
+      depsSrc,
+      cargoToml, # Workspace root Cargo.toml
+      name ? "workspace",
+      members ? null, # Member crate names to check (default: all members)
+      checks ? [
+        "clippy"
+        "test"
+        "doc"
+      ],
+      args ? { }, # Extra mkRustPackage arguments for every check
+      memberArgs ? { }, # Extra mkRustPackage arguments per member, keyed by crate name
+    }:
+    let
+      modeArgsOf = attrs: builtins.filter (argName: attrs ? ${argName}) modeArgNames;
+      conflictingArgs = lib.unique (
+        modeArgsOf args ++ lib.concatMap modeArgsOf (builtins.attrValues memberArgs)
+      );
+
+      mkChecks =
+        mode:
+        let
+          workspace = mkRustWorkspace {
+            inherit
+              builder
+              src
+              depsSrc
+              cargoToml
+              name
+              members
+              ;
+            package = mkRustPackage;
+            # Restrict cargo test and cargo doc to the member selected with -p
+            args = args // {
+              inherit mode;
+              cargoTestExtraArgs = args.cargoTestExtraArgs or "";
+            };
+            memberArgs =
+              memberName:
+              lib.optionalAttrs (mode == "doc") { cargoDocExtraArgs = "-p ${memberName} --no-deps"; }
+              // memberArgs.${memberName} or { };
+          };
+        in
+        lib.mapAttrs' (memberName: check: lib.nameValuePair "${mode}-${memberName}" check) workspace;
+    in
+    assert lib.assertMsg (conflictingArgs == [ ])
+      "mkWorkspaceChecks: the modes are selected by `checks`, remove ${lib.concatStringsSep ", " conflictingArgs} from args and memberArgs";
+    lib.foldl' (acc: mode: acc // mkChecks mode) { } checks;
+
+  # Toolchain Matrix
+  # ----------------
+  # Build the same package definition with several Rust toolchains
+
+  # Create one check derivation per toolchain
+  # Returns an attrset ready to merge into `checks`, e.g.
+  #   { test-stable = <drv>; test-beta = <drv>; test-nightly = <drv>; test-msrv = <drv>; }
+  #
+  # Toolchains are "stable", "beta", "nightly" (pinned by nightlyDate when set),
+  # "msrv" (the `rust-version` declared in cargoToml) or an explicit stable
+  # version such as "1.83.0".
+  #
+  # The checks run the package's tests unless `args` selects another mode
+  # (e.g. mode = "clippy" with namePrefix = "clippy").
+  mkToolchainChecks =
+    {
+      args, # Arguments for the package function (e.g. mkRustPackage args, incl. cargoToml)
+      package ? mkRustPackage, # Package function passed to builder.callPackage
+      toolchains ? [
+        "stable"
+        "beta"
+        "nightly"
+        "msrv"
+      ],
+      namePrefix ? "test", # Checks are named "<namePrefix>-<toolchain>"
+      nightlyDate ? null,
+      localSystem ? system,
+    }:
+    let
+      # Run the tests when args doesn't select a mode itself
+      checkArgs =
+        if builtins.any (argName: args ? ${argName}) modeArgNames then args else args // { mode = "test"; };
+
+      manifest = builtins.fromTOML (builtins.readFile args.cargoToml);
+      declaredMsrv =
+        let
+          packageMsrv = manifest.package.rust-version or null;
+        in
+        if builtins.isString packageMsrv then
+          packageMsrv
+        else
+          manifest.workspace.package.rust-version or (throw
+            "mkToolchainChecks: ${toString args.cargoToml} does not declare a rust-version"
+          );
+      # rust-overlay only knows full versions, while `rust-version` may omit the patch level
+      msrv =
+        if builtins.length (builtins.splitVersion declaredMsrv) == 2 then
+          "${declaredMsrv}.0"
+        else
+          declaredMsrv;
+
+      toolchainArgs =
+        toolchain:
+        if toolchain == "stable" || toolchain == "beta" then
+          { channel = toolchain; }
+        else if toolchain == "nightly" then
+          {
+            useRustNightly = true;
+            inherit nightlyDate;
+          }
+        else if toolchain == "msrv" then
+          { rustVersion = msrv; }
+        else
+          { rustVersion = toolchain; };
+
+      mkCheck =
+        toolchain:
+        let
+          builder = mkRustBuilder ({ inherit localSystem; } // toolchainArgs toolchain);
+        in
+        {
+          name = "${namePrefix}-${toolchain}";
+          value = builder.callPackage package checkArgs;
+        };
+    in
+    builtins.listToAttrs (map mkCheck toolchains);
+
+  # Test Sharding
+  # -------------
+  # Split a test suite across several derivations
+
+  # Create one check derivation per nextest partition
+  # Returns an attrset ready to merge into `checks`, e.g.
+  #   { test-shard-1 = <drv>; test-shard-2 = <drv>; test-shard-3 = <drv>; }
+  #
+  # The tests are compiled once into a nextest archive, every shard runs its
+  # partition (`--partition count:<i>/<shards>`) of the archived tests. The
+  # shards don't depend on each other, so they can run in parallel and a
+  # failing shard can be rebuilt on its own.
+  mkTestShards =
+    {
+      builder,
+      args, # mkRustPackage arguments (incl. cargoToml), as for mode = "nextest"
+      shards, # Number of shards
+      namePrefix ? "test-shard", # Checks are named "<namePrefix>-<i>"
+    }:
+    let
+      # Retries and quarantine only affect how the shards run the tests
+      archiveArgs = builtins.removeAttrs args [
+        "nextestRetries"
+        "quarantinedTests"
+        "nextestAllowFailures"
+      ];
+      archive = builder.callPackage mkRustPackage (archiveArgs // { mode = "nextest-archive"; });
+
+      mkShard = index: {
+        name = "${namePrefix}-${toString index}";
+        value = builder.callPackage mkRustPackage (
+          args
+          // {
+            mode = "nextest";
+            nextestArchive = archive;
+            nextestPartition = "count:${toString index}/${toString shards}";
+          }
+        );
+      };
+    in
+    assert lib.assertMsg (builtins.isInt shards && shards >= 1)
+      "mkTestShards: shards must be a positive integer, got ${builtins.toJSON shards}";
+    builtins.listToAttrs (map mkShard (lib.range 1 shards));
+
   # Docker Images
   # ------------
   # Functions for creating Docker container images
@@ -169,6 +466,9 @@ rec {
     {
       rustToolchain ? null,
       rustToolchainFile ? null,
+      channel ? "stable",
+      rustVersion ? null,
+      nightlyDate ? null,
       extraPackages ? [ ],
       shellName ? "Development",
       shellHook ? "",
@@ -178,6 +478,7 @@ rec {
       postgresPackage ? null,
       withLlvmTools ? false,
       includeCiPackages ? true,
+      withProtoc ? false,
     }:
     import ./shells.nix {
       inherit
@@ -186,6 +487,9 @@ rec {
         ciTools
         rustToolchain
         rustToolchainFile
+        channel
+        rustVersion
+        nightlyDate
         extraPackages
         shellName
         shellHook
@@ -195,6 +499,7 @@ rec {
         postgresPackage
         withLlvmTools
         includeCiPackages
+        withProtoc
         ;
       pkgsUnstable = pkgsUnstable;
     };
@@ -210,6 +515,9 @@ rec {
       globalExcludes ? [ ],
       extraFormatters ? { },
       projectRootFile ? "flake.nix",
+      channel ? "stable",
+      rustVersion ? null,
+      nightlyDate ? null,
     }:
     import ./treefmt.nix {
       inherit
@@ -218,6 +526,9 @@ rec {
         globalExcludes
         extraFormatters
         projectRootFile
+        channel
+        rustVersion
+        nightlyDate
         ;
     };
 
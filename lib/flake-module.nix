@@ -71,6 +71,30 @@
           description = "File used to identify the project root for treefmt";
           example = "Cargo.toml";
         };
+
+        channel = lib.mkOption {
+          type = lib.types.enum [
+            "stable"
+            "beta"
+            "nightly"
+          ];
+          default = "stable";
+          description = "Toolchain channel providing rustfmt when a toolchain is pinned";
+        };
+
+        rustVersion = lib.mkOption {
+          type = lib.types.nullOr lib.types.str;
+          default = null;
+          description = "Pinned toolchain version providing rustfmt (latest nightly when unset)";
+          example = "1.83.0";
+        };
+
+        nightlyDate = lib.mkOption {
+          type = lib.types.nullOr lib.types.str;
+          default = null;
+          description = "Pinned nightly date providing rustfmt (latest nightly when unset)";
+          example = "2026-09-01";
+        };
       };
 
       # Apply configuration
@@ -81,6 +105,7 @@
           globalExcludes = config.nix-lib.treefmt.globalExcludes;
           extraFormatters = config.nix-lib.treefmt.extraFormatters;
           projectRootFile = config.nix-lib.treefmt.projectRootFile;
+          inherit (config.nix-lib.treefmt) channel rustVersion nightlyDate;
         };
 
         # Export the formatter for nix fmt
@@ -0,0 +1,110 @@
+# native-libs.nix - Native C libraries for *-sys crates
+#
+# Resolves the C libraries requested through `nativeLibs` and returns the build
+# inputs and environment variables their *-sys crates need to find the right
+# library for native, cross and static builds.
+#
+# Like openssl, every library is provided twice when cross-compiling: for the
+# target and for the build platform, where build scripts and proc-macros (e.g.
+# sqlx-macros with sqlite) are compiled. pkg-config is scoped per Rust target,
+# so each side resolves the library directories, include directories and link
+# flags of its own architecture.
+#
+# This is a low-level building block used by rust-package.nix and
+# rust-library.nix.
+
+{
+  lib, # Nixpkgs lib utilities
+  pkgs, # Package set of the builder (cross package set for cross builds)
+  nativeLibs ? [ ], # Library names, e.g. [ "zlib" "sqlite" ]
+  isCross ? false, # Whether this is cross-compilation
+  isStatic ? false, # Whether to link the libraries statically
+  isWasm ? false, # Whether this targets WebAssembly
+  rustTarget ? null, # Rust target triple (set by the builder)
+  buildRustTarget ? null, # Rust triple of the build platform (set by the builder)
+}:
+let
+  # Supported libraries
+  #   package: Selects the library from a package set
+  #   env: Variables for the *-sys crate to use the system library
+  #   staticEnv: Additional variables for static builds
+  libraries = {
+    zlib = {
+      package = p: p.zlib;
+      staticEnv.LIBZ_SYS_STATIC = "1";
+    };
+    zstd = {
+      package = p: p.zstd;
+      env.ZSTD_SYS_USE_PKG_CONFIG = "1";
+    };
+    sqlite = {
+      package = p: p.sqlite;
+      staticEnv.SQLITE3_STATIC = "1";
+    };
+    libpq = {
+      package = p: p.libpq;
+      staticEnv.PQ_LIB_STATIC = "1";
+    };
+    libgit2 = {
+      package = p: p.libgit2;
+      env.LIBGIT2_NO_VENDOR = "1";
+      staticEnv = {
+        LIBGIT2_STATIC = "1";
+        LIBSSH2_STATIC = "1";
+      };
+    };
+  };
+
+  unknownLibs = builtins.filter (name: !(libraries ? ${name})) nativeLibs;
+  selected = map (name: libraries.${name}) nativeLibs;
+
+  targetPkgs = if isStatic then pkgs.pkgsStatic else pkgs;
+  targetLibs = map (library: library.package targetPkgs) selected;
+  buildHostLibs = map (library: library.package pkgs.pkgsBuildHost) selected;
+
+  pkgConfigPath = libs: lib.makeSearchPathOutput "dev" "lib/pkgconfig" libs;
+
+  # The pkg-config crate prefers `PKG_CONFIG_<target>` and
+  # `PKG_CONFIG_PATH_<target>` over the unscoped variables.
+  targetSuffix = triple: builtins.replaceStrings [ "-" ] [ "_" ] triple;
+
+  crossEnv = lib.optionalAttrs (isCross && nativeLibs != [ ]) (
+    {
+      PKG_CONFIG_ALLOW_CROSS = "1";
+      "PKG_CONFIG_${targetSuffix rustTarget}" =
+        "${pkgs.pkgsBuildHost.pkg-config}/bin/${pkgs.stdenv.cc.targetPrefix}pkg-config";
+      "PKG_CONFIG_PATH_${targetSuffix rustTarget}" = pkgConfigPath targetLibs;
+    }
+    // lib.optionalAttrs (buildRustTarget != rustTarget) {
+      "PKG_CONFIG_${targetSuffix buildRustTarget}" =
+        "${pkgs.pkgsBuildBuild.pkg-config}/bin/pkg-config";
+      "PKG_CONFIG_PATH_${targetSuffix buildRustTarget}" = pkgConfigPath buildHostLibs;
+    }
+  );
+
+  # Static variables are honoured by pkg-config only where a static archive
+  # exists, so the dynamic build platform libraries are unaffected.
+  # LIBZ_SYS_STATIC makes libz-sys compile its bundled zlib statically
+  # instead, on both sides, which doesn't need a library from nixpkgs.
+  staticEnv = lib.optionalAttrs (isStatic && nativeLibs != [ ]) (
+    { PKG_CONFIG_ALL_STATIC = "1"; }
+    // lib.mergeAttrsList (map (library: library.staticEnv or { }) selected)
+  );
+in
+assert lib.assertMsg (unknownLibs == [ ])
+  "nativeLibs: unsupported libraries ${lib.concatStringsSep ", " unknownLibs}, expected any of: ${lib.concatStringsSep ", " (builtins.attrNames libraries)}";
+assert lib.assertMsg (
+  nativeLibs == [ ] || !isWasm
+) "nativeLibs: native C libraries cannot be linked into wasm targets";
+assert lib.assertMsg (
+  nativeLibs == [ ] || !isCross || (rustTarget != null && buildRustTarget != null)
+) "nativeLibs: cross builds have to be created with builder.callPackage";
+{
+  # Target libraries, linked into the package
+  buildInputs = targetLibs;
+
+  # Build platform libraries for build scripts and proc-macros
+  nativeBuildInputs = lib.optionals isCross buildHostLibs;
+
+  env = lib.mergeAttrsList (map (library: library.env or { }) selected) // crossEnv // staticEnv;
+}
@@ -17,7 +17,17 @@
   rust-overlay, # Rust toolchain overlay
   useRustNightly ? false, # Whether to use nightly Rust toolchain
   rustToolchainFile ? null, # Optional path to rust-toolchain.toml
+  channel ? "stable", # Toolchain channel: stable, beta or nightly
+  rustVersion ? null, # Pinned stable version (e.g. "1.83.0") or beta date
+  nightlyDate ? null, # Pinned nightly date (e.g. "2026-09-01") for nightly toolchains
   withLlvmTools ? false, # Whether to include llvm-tools for code coverage
+  rustflags ? [ ], # Extra flags appended to CARGO_BUILD_RUSTFLAGS
+  extraEnv ? { }, # Extra environment variables for all derivations built with this builder
+  rustTarget ? null, # Rust target built with Rust's bundled toolchain instead of a nixpkgs cross stdenv (wasm)
+  glibcVersion ? null, # Minimum glibc version for dynamic glibc targets, links with zig when set
+  linker ? null, # Linker backend: mold, lld, bfd or wild (default: mold/lld for native builds)
+  defaultLinker ? null, # Linker backend used instead of the default where the target supports it
+  cxxStdlib ? "stdc++", # C++ runtime linked statically into static Linux binaries, null to leave it to the crates
 }@args:
 let
   crossSystem0 = crossSystem;
@@ -35,7 +45,9 @@ let
     let
       system = pkgsLocal.lib.systems.elaborate crossSystem0;
     in
-    if crossSystem0 == null || pkgsLocal.lib.systems.equals system localSystem then
+    if
+      rustTarget != null || crossSystem0 == null || pkgsLocal.lib.systems.equals system localSystem
+    then
       localSystem
     else
       system;
@@ -54,32 +66,43 @@ let
 
   envCase = triple: pkgsLocal.lib.strings.toUpper (builtins.replaceStrings [ "-" ] [ "_" ] triple);
 
-  cargoTarget =
-    if hostPlatform.config == "arm64-apple-darwin" then "aarch64-apple-darwin" else hostPlatform.config;
+  # Rust target triples mostly match the nixpkgs platform config, except for a
+  # few architectures where the naming schemes differ.
+  rustTargetOverrides = {
+    "arm64-apple-darwin" = "aarch64-apple-darwin";
+    "armv7l-unknown-linux-gnueabihf" = "armv7-unknown-linux-gnueabihf";
+    "armv7l-unknown-linux-musleabihf" = "armv7-unknown-linux-musleabihf";
+    "riscv64-unknown-linux-gnu" = "riscv64gc-unknown-linux-gnu";
+    "riscv64-unknown-linux-musl" = "riscv64gc-unknown-linux-musl";
+    "x86_64-w64-mingw32" = "x86_64-pc-windows-gnu";
+  };
+  toRustTarget = platform: rustTargetOverrides.${platform.config} or platform.config;
+
+  cargoTarget = if rustTarget != null then rustTarget else toRustTarget hostPlatform;
+
+  # wasm targets link with rust-lld and ship their own (WASI) libc, so none of
+  # the native C toolchain, linker or openssl settings apply to them.
+  isWasm = pkgsLocal.lib.hasPrefix "wasm32-" cargoTarget;
+  isWasi = pkgsLocal.lib.hasPrefix "wasm32-wasi" cargoTarget;
 
   llvmToolsExtensions = if withLlvmTools then [ "llvm-tools-preview" ] else [ ];
 
+  rustToolchain = import ./rust-toolchain.nix {
+    inherit
+      rustToolchainFile
+      channel
+      rustVersion
+      nightlyDate
+      ;
+  };
+
   rustToolchainFun =
-    if useRustNightly then
-      p:
-      p.rust-bin.selectLatestNightlyWith (
-        toolchain:
-        toolchain.default.override {
-          extensions = llvmToolsExtensions;
-        }
-      )
-    else if rustToolchainFile != null then
-      p:
-      (p.rust-bin.fromRustupToolchainFile rustToolchainFile).override {
-        targets = [ cargoTarget ];
-        extensions = llvmToolsExtensions;
-      }
-    else
-      p:
-      p.rust-bin.stable.latest.default.override {
-        targets = [ cargoTarget ];
-        extensions = llvmToolsExtensions;
-      };
+    p:
+    rustToolchain.select p.rust-bin {
+      nightly = useRustNightly;
+      targets = [ cargoTarget ];
+      extensions = llvmToolsExtensions;
+    };
 
   craneLibBase = (crane.mkLib pkgs).overrideToolchain rustToolchainFun;
 
@@ -99,31 +122,78 @@ let
     else
       craneLibBase;
 
-  # mold is only supported on Linux builds, so falling back to lld for Darwin
-  linker = if buildPlatform.isDarwin then "lld" else "mold";
+  # Linker backends and the targets they can link for. mold, lld and bfd are
+  # selected through the C compiler driver (`-fuse-ld`), wild is driven by
+  # clang's `--ld-path` and only supports native Linux builds.
+  supportedLinkers = {
+    mold = buildPlatform.isLinux && hostPlatform.isLinux;
+    lld = hostPlatform.isLinux || hostPlatform.isDarwin || hostPlatform.isWindows;
+    bfd = buildPlatform.isLinux && (hostPlatform.isLinux || hostPlatform.isWindows);
+    wild = buildPlatform.isLinux && hostPlatform.isLinux && !isCross;
+  };
+
+  # Without an explicit choice native builds use mold (lld on Darwin, where
+  # mold is not supported) and cross builds use the toolchain's default linker.
+  # A default linker (e.g. from mkRustBuilders) only applies to the targets
+  # it can link for, instead of failing for the others.
+  useDefaultLinker =
+    defaultLinker != null
+    && !(isWasm || glibcVersion != null)
+    && supportedLinkers.${defaultLinker} or false;
+  selectedLinker =
+    if linker != null then
+      linker
+    else if useDefaultLinker then
+      defaultLinker
+    else if isCross then
+      null
+    else if buildPlatform.isDarwin then
+      "lld"
+    else
+      "mold";
 
   buildEnvBase = {
     CARGO_BUILD_TARGET = cargoTarget;
-    "CARGO_TARGET_${envCase cargoTarget}_LINKER" = "${pkgs.stdenv.cc.targetPrefix}cc";
     HOST_CC = "${pkgs.stdenv.cc.nativePrefix}cc";
+  }
+  // pkgsLocal.lib.optionalAttrs (!isWasm && !zigLinkerInRustflags) {
+    "CARGO_TARGET_${envCase cargoTarget}_LINKER" =
+      if selectedLinker == "wild" then
+        "${pkgs.pkgsBuildHost.clang}/bin/clang"
+      else
+        "${pkgs.stdenv.cc.targetPrefix}cc";
   };
-  buildEnvCross =
-    if isCross then
-      {
-        # For cross-compilation, don't use mold/lld as it can cause issues
-        CARGO_BUILD_RUSTFLAGS = "";
-      }
-    else
-      {
-        CARGO_BUILD_RUSTFLAGS = "-C link-arg=-fuse-ld=${linker}";
-      };
-  buildEnvStatic =
-    if isStatic then
-      {
-        CARGO_BUILD_RUSTFLAGS = "${buildEnvCross.CARGO_BUILD_RUSTFLAGS} -C target-feature=+crt-static";
-      }
+
+  # Rust flags are collected as a list, so the linker, crt-static and extra
+  # flags (from the builder and from the package) are merged instead of
+  # overwriting each other.
+  linkerRustflags =
+    if selectedLinker == null then
+      [ ]
+    else if selectedLinker == "wild" then
+      [
+        "-C"
+        "link-arg=--ld-path=${pkgs.pkgsBuildHost.wild}/bin/wild"
+      ]
     else
-      { };
+      [
+        "-C"
+        "link-arg=-fuse-ld=${selectedLinker}"
+      ];
+  staticRustflags = pkgsLocal.lib.optionals isStatic [
+    "-C"
+    "target-feature=+crt-static"
+  ];
+  builderRustflags =
+    linkerRustflags
+    ++ glibcRustflags
+    ++ staticRustflags
+    ++ rustflags
+    ++ pkgsLocal.lib.optional (extraEnv ? CARGO_BUILD_RUSTFLAGS) extraEnv.CARGO_BUILD_RUSTFLAGS;
+
+  buildEnvRustflags = {
+    CARGO_BUILD_RUSTFLAGS = pkgsLocal.lib.concatStringsSep " " builderRustflags;
+  };
 
   # When cross-compiling, proc-macros (e.g. sqlx-macros) are compiled for the
   # build platform but openssl-sys's build script finds the target platform's
@@ -132,14 +202,10 @@ let
   # library for each architecture, and disable pkg-config to prevent conflicts.
   targetOpenssl = if isStatic then pkgs.pkgsStatic.openssl else pkgs.openssl;
   buildHostOpenssl = pkgsLocal.openssl;
-  buildHostTarget =
-    if buildPlatform.config == "arm64-apple-darwin" then
-      "aarch64-apple-darwin"
-    else
-      buildPlatform.config;
+  buildHostTarget = toRustTarget buildPlatform;
 
   buildEnvOpenssl =
-    if isCross then
+    if isCross && !isWasm then
       {
         OPENSSL_NO_PKG_CONFIG = "1";
         "${envCase cargoTarget}_OPENSSL_LIB_DIR" = "${targetOpenssl.out}/lib";
@@ -161,15 +227,289 @@ let
     else
       { };
 
-  buildEnv = buildEnvBase // buildEnvCross // buildEnvStatic // buildEnvOpenssl;
+  # Windows test binaries are executed under Wine. Wine needs a writable prefix
+  # (HOME is not writable in the sandbox) and has to find the runtime DLLs of
+  # the target libraries, which it resolves through WINEPATH.
+  windowsRuntimeDlls = [
+    pkgs.windows.pthreads
+    pkgs.openssl
+  ];
+  wineRunner = pkgsLocal.writeShellScript "wine-runner" ''
+    export WINEPREFIX="$(mktemp -d)"
+    export WINEDEBUG=-all
+    export WINEPATH="${
+      pkgsLocal.lib.concatMapStringsSep ";" (
+        dep: "Z:${pkgsLocal.lib.getBin dep}/bin"
+      ) windowsRuntimeDlls
+    }"
+    exec ${pkgsLocal.wineWowPackages.stable}/bin/wine64 "$@"
+  '';
+
+  # Wine can only execute x86_64 Windows binaries on x86_64 Linux hosts
+  buildEnvWindows =
+    if hostPlatform.isWindows && buildPlatform.isLinux && buildPlatform.isx86_64 then
+      {
+        "CARGO_TARGET_${envCase cargoTarget}_RUNNER" = "${wineRunner}";
+      }
+    else
+      { };
+
+  # Cross-compiled Linux binaries the build platform can't execute natively
+  # (tests, benchmarks) are run under QEMU user-mode emulation. nixpkgs
+  # provides the matching `qemu-<arch>` for the target platform.
+  needsEmulator =
+    hostPlatform.isLinux
+    && buildPlatform.isLinux
+    && isCross
+    && !isWasm
+    && !buildPlatform.canExecute hostPlatform;
+  buildEnvQemu =
+    if needsEmulator then
+      {
+        "CARGO_TARGET_${envCase cargoTarget}_RUNNER" = hostPlatform.emulator pkgsLocal;
+      }
+    else
+      { };
+
+  # glibc targets with a pinned minimum version are linked by zig (through
+  # cargo-zigbuild's `zig cc` wrapper) against the glibc symbol versions of
+  # that release, instead of the newer glibc shipped by nixpkgs. The resulting
+  # binaries use the standard FHS loader and run on stock distributions.
+  zigTarget =
+    if hostPlatform.isAarch32 then
+      "arm-linux-gnueabihf"
+    else if hostPlatform.isx86_32 then
+      "x86-linux-gnu"
+    else
+      "${hostPlatform.parsed.cpu.name}-linux-gnu";
+  mkZigTool =
+    tool: extraArgs:
+    pkgsLocal.writeShellScript "zig${tool}-${cargoTarget}" ''
+      export PATH="${pkgsLocal.zig}/bin:$PATH"
+      # zig needs a writable cache, HOME is not writable in the sandbox
+      export ZIG_GLOBAL_CACHE_DIR="''${ZIG_GLOBAL_CACHE_DIR:-''${TMPDIR:-/tmp}/zig-cache}"
+      export ZIG_LOCAL_CACHE_DIR="$ZIG_GLOBAL_CACHE_DIR"
+      exec ${pkgsLocal.cargo-zigbuild}/bin/cargo-zigbuild zig ${tool} -- ${extraArgs} "$@"
+    '';
+  zigCc = mkZigTool "cc" "-target ${zigTarget}.${glibcVersion} -g";
+  zigCxx = mkZigTool "c++" "-target ${zigTarget}.${glibcVersion} -g";
+  zigAr = mkZigTool "ar" "";
+  zigRanlib = mkZigTool "ranlib" "";
+
+  # zig-linked binaries reference the FHS loader, which doesn't exist in the
+  # build sandbox, so tests run through the loader of the target glibc
+  # (under QEMU when the build platform can't execute them).
+  glibcRunner = pkgsLocal.writeShellScript "glibc-runner" ''
+    exec ${
+      pkgsLocal.lib.optionalString needsEmulator "${hostPlatform.emulator pkgsLocal} "
+    }${pkgs.stdenv.cc.libc}/lib/${baseNameOf pkgs.stdenv.cc.bintools.dynamicLinker} "$@"
+  '';
+
+  # When the target is the build platform's own triple (x86_64-linux-gnu on
+  # an x86_64 host), cargo also applies `CARGO_TARGET_<triple>_LINKER` to build
+  # scripts and proc-macros, which then couldn't be loaded in the sandbox. zig
+  # is passed with the Rust flags instead, which only apply to the target's
+  # artifacts because CARGO_BUILD_TARGET is set. The runner is only used for
+  # target binaries either way. Objects compiled by zig for build scripts (the
+  # CC_<triple> settings can't tell them apart) link fine with the newer glibc.
+  zigLinkerInRustflags = glibcVersion != null && cargoTarget == buildHostTarget;
+  glibcRustflags = pkgsLocal.lib.optionals zigLinkerInRustflags [
+    "-C"
+    "linker=${zigCc}"
+  ];
+
+  buildEnvGlibc =
+    if glibcVersion != null then
+      {
+        "CARGO_TARGET_${envCase cargoTarget}_RUNNER" = "${glibcRunner}";
+      }
+      // pkgsLocal.lib.optionalAttrs (!zigLinkerInRustflags) {
+        "CARGO_TARGET_${envCase cargoTarget}_LINKER" = "${zigCc}";
+      }
+    else
+      { };
+
+  # C/C++ toolchain for build scripts compiling C code for the target through
+  # cc-rs or cmake (ring, aws-lc-sys, blake3, rocksdb, ...). Without explicit
+  # target-specific settings they may fall back to the build platform's
+  # compiler or archiver. glibc targets use the zig wrappers instead.
+  targetCcTools =
+    if glibcVersion != null then
+      {
+        cc = "${zigCc}";
+        cxx = "${zigCxx}";
+        ar = "${zigAr}";
+        ranlib = "${zigRanlib}";
+      }
+    else
+      let
+        targetPrefix = pkgs.stdenv.cc.targetPrefix;
+      in
+      {
+        cc = "${pkgs.stdenv.cc}/bin/${targetPrefix}cc";
+        cxx = "${pkgs.stdenv.cc}/bin/${targetPrefix}c++";
+        ar = "${pkgs.stdenv.cc.bintools}/bin/${targetPrefix}ar";
+        ranlib = "${pkgs.stdenv.cc.bintools}/bin/${targetPrefix}ranlib";
+      };
+
+  cmakeToolchainFile = pkgsLocal.writeText "cmake-toolchain-${cargoTarget}.cmake" ''
+    set(CMAKE_SYSTEM_NAME ${hostPlatform.uname.system})
+    set(CMAKE_SYSTEM_PROCESSOR ${hostPlatform.uname.processor})
+    set(CMAKE_C_COMPILER ${targetCcTools.cc})
+    set(CMAKE_CXX_COMPILER ${targetCcTools.cxx})
+    set(CMAKE_AR ${targetCcTools.ar} CACHE FILEPATH "Archiver")
+    set(CMAKE_RANLIB ${targetCcTools.ranlib} CACHE FILEPATH "Ranlib")
+    set(CMAKE_FIND_ROOT_PATH_MODE_PROGRAM NEVER)
+  '';
+
+  # cc-rs and the cmake crate read both `<VAR>_<triple>` spellings, the
+  # underscored one is also a valid shell variable name.
+  #
+  # The target flags take precedence over the unscoped CFLAGS and CXXFLAGS,
+  # which belong to the build platform compiler. Rust links static-pie
+  # executables for static targets, so C and C++ objects have to be position
+  # independent there.
+  buildEnvCc =
+    let
+      triple = builtins.replaceStrings [ "-" ] [ "_" ] cargoTarget;
+      targetFlags = pkgsLocal.lib.optionalString isStatic "-fPIC";
+    in
+    if isCross && !isWasm then
+      {
+        "CC_${triple}" = targetCcTools.cc;
+        "CXX_${triple}" = targetCcTools.cxx;
+        "AR_${triple}" = targetCcTools.ar;
+        "RANLIB_${triple}" = targetCcTools.ranlib;
+        "CFLAGS_${triple}" = targetFlags;
+        "CXXFLAGS_${triple}" = targetFlags;
+        "CMAKE_TOOLCHAIN_FILE_${triple}" = "${cmakeToolchainFile}";
+        HOST_CXX = "${pkgs.stdenv.cc.nativePrefix}c++";
+        HOST_AR = "${pkgs.stdenv.cc.nativePrefix}ar";
+      }
+    else
+      { };
+
+  # cc-rs links the C++ runtime of C++ code (rocksdb, librdkafka, duckdb, ...)
+  # as a dynamic library, which fails to link or yields a dynamic binary with
+  # crt-static. Static Linux builders link it statically instead. It is not
+  # bundled into the -sys crate's rlib, so the C compiler driver resolves the
+  # archive from its own library path at the final link.
+  staticCxxStdlib = if isStatic && hostPlatform.isLinux then cxxStdlib else null;
+  buildEnvCxx =
+    if staticCxxStdlib != null then
+      {
+        "CXXSTDLIB_${builtins.replaceStrings [ "-" ] [ "_" ] cargoTarget}" =
+          "static:-bundle=${staticCxxStdlib}";
+      }
+    else
+      { };
+
+  # WASI test binaries are executed with wasmtime, with the build directory
+  # preopened so tests can access their fixtures.
+  buildEnvWasm =
+    if isWasi then
+      {
+        "CARGO_TARGET_${envCase cargoTarget}_RUNNER" = "${pkgsLocal.wasmtime}/bin/wasmtime run --dir=.";
+      }
+    else
+      { };
+
+  buildEnv =
+    buildEnvBase
+    // buildEnvRustflags
+    // buildEnvOpenssl
+    // buildEnvCc
+    // buildEnvCxx
+    // buildEnvWindows
+    // buildEnvQemu
+    // buildEnvWasm
+    // buildEnvGlibc
+    // builtins.removeAttrs extraEnv [ "CARGO_BUILD_RUSTFLAGS" ];
+
+  # Apply the builder environment on top of a package derivation. Rust flags
+  # set by the package are appended to the builder's flags rather than being
+  # replaced. RUSTFLAGS takes precedence over CARGO_BUILD_RUSTFLAGS in cargo,
+  # so when a package sets it, the builder's flags are prepended there too.
+  #
+  # The package's own flags are kept in packageRustflags (and packageRUSTFLAGS),
+  # so applying the environment again (e.g. to dependency artifacts shared by
+  # a workspace) starts from the same flags and doesn't change the derivation.
+  applyBuildEnv =
+    previous:
+    let
+      packageRustflags =
+        previous.packageRustflags or (toString (previous.CARGO_BUILD_RUSTFLAGS or ""));
+      packageRUSTFLAGS = previous.packageRUSTFLAGS or previous.RUSTFLAGS or null;
+
+      withBuilderRustflags =
+        flags:
+        pkgsLocal.lib.concatStringsSep " " (
+          builtins.filter (flag: flag != "") [
+            buildEnv.CARGO_BUILD_RUSTFLAGS
+            (toString flags)
+          ]
+        );
+    in
+    buildEnv
+    // {
+      inherit packageRustflags;
+      CARGO_BUILD_RUSTFLAGS = withBuilderRustflags packageRustflags;
+    }
+    // pkgsLocal.lib.optionalAttrs (packageRUSTFLAGS != null) {
+      inherit packageRUSTFLAGS;
+      RUSTFLAGS = withBuilderRustflags packageRUSTFLAGS;
+    };
+
+  # Builder properties that are only passed to packages declaring them, so
+  # existing package functions keep working with builder.callPackage.
+  optionalPackageArgs = {
+    inherit isWasm glibcVersion;
+    linker = selectedLinker;
+    rustTarget = cargoTarget;
+    buildRustTarget = buildHostTarget;
+  };
 
 in
+assert pkgsLocal.lib.assertMsg (glibcVersion == null || (hostPlatform.isLinux && hostPlatform.isGnu))
+  "rust-builder: glibcVersion is only supported for glibc Linux targets, not ${hostPlatform.config}";
+assert pkgsLocal.lib.assertMsg (
+  linker == null || supportedLinkers ? ${linker}
+) "rust-builder: unknown linker '${toString linker}', expected one of: mold, lld, bfd, wild";
+assert pkgsLocal.lib.assertMsg (
+  defaultLinker == null || supportedLinkers ? ${defaultLinker}
+) "rust-builder: unknown linker '${toString defaultLinker}', expected one of: mold, lld, bfd, wild";
+assert pkgsLocal.lib.assertMsg (
+  linker == null || !(isWasm || glibcVersion != null)
+) "rust-builder: linker cannot be set for ${cargoTarget}, the target brings its own linker";
+assert pkgsLocal.lib.assertMsg (linker == null || supportedLinkers.${linker} or false)
+  "rust-builder: linker '${toString linker}' is not supported when building for ${cargoTarget} on ${buildPlatform.config}";
 {
+  # Builder metadata, so downstream code can branch on the target instead of
+  # parsing builder names
+  rustTarget = cargoTarget; # Rust target triple (e.g. "aarch64-unknown-linux-musl")
+  inherit
+    isCross
+    isStatic
+    isWasm
+    glibcVersion
+    hostPlatform
+    ;
+  linker = selectedLinker; # Linker backend, null when the toolchain default is used
+  cxxStdlib = staticCxxStdlib; # Statically linked C++ runtime, null when not linked statically
+  channel = if useRustNightly then "nightly" else channel;
+  toolchain = rustToolchainFun pkgs.pkgsBuildHost; # Rust toolchain used by craneLib, built for the build platform
+
+  # Underlying package set (cross pkgs for cross builders) and crane library
+  inherit pkgs craneLib;
+
   callPackage = (
     package: args:
     let
+      packageFn =
+        if builtins.isPath package || builtins.isString package then import package else package;
       crate = pkgs.callPackage package (
         args
+        // builtins.intersectAttrs (pkgsLocal.lib.functionArgs packageFn) optionalPackageArgs
         // {
           inherit
             craneLib
@@ -182,12 +522,12 @@ in
     # Override the derivation to add cross-compilation environment variables.
     crate.overrideAttrs (
       previous:
-      buildEnv
+      applyBuildEnv previous
       // {
         # We also have to override the `cargoArtifacts` derivation with the same changes.
         cargoArtifacts =
           if previous.cargoArtifacts != null then
-            previous.cargoArtifacts.overrideAttrs (previous: buildEnv)
+            previous.cargoArtifacts.overrideAttrs applyBuildEnv
           else
             null;
       }
@@ -14,20 +14,83 @@
   crane,
   localSystem,
   rustToolchainFile ? null, # Optional path to rust-toolchain.toml
+  channel ? "stable", # Default toolchain channel: stable, beta or nightly
+  rustVersion ? null, # Pinned stable version (e.g. "1.83.0") or beta date
+  nightlyDate ? null, # Pinned nightly date (e.g. "2026-09-01") for nightly builders
+  linker ? null, # Default linker backend for all builders: mold, lld, bfd or wild
 }:
 
+let
+  pkgsLocal = import nixpkgs { inherit localSystem; };
+  lib = pkgsLocal.lib;
+
+  defaultChannel = channel;
+  defaultLinker = linker;
+
+  # musl variants of the armv7 (hard-float) and riscv64 platforms
+  armv7MuslSystem = {
+    config = "armv7l-unknown-linux-musleabihf";
+  };
+  riscv64MuslSystem = {
+    config = "riscv64-unknown-linux-musl";
+  };
+in
 rec {
-  # Create a Rust builder for the local platform
-  # This is the default builder used for development
+  # Create a Rust builder from a declarative target description
+  # This is the generic building block behind all other builder functions, so
+  # custom targets get the same toolchain, linker and openssl handling as the
+  # built-in ones.
   #
   # Arguments:
-  #   useRustNightly: Whether to use nightly Rust toolchain (default: false)
+  #   crossSystem: Target triple (e.g. "aarch64-unknown-linux-musl") or a nixpkgs
+  #     system attrset (e.g. lib.systems.examples.musl64). null means the local
+  #     platform. wasm32 triples (e.g. "wasm32-wasip1") are Rust target names
+  #     and are built without a nixpkgs cross toolchain (default: null)
+  #   isStatic: Whether to create statically linked binaries (default: false)
+  #   channel: Rust toolchain channel, "stable", "beta" or "nightly". Nightly
+  #     builders use the pinned nightlyDate, rustVersion only applies to the
+  #     default channel (default: the channel passed to mkRustBuilders)
   #   withLlvmTools: Whether to include llvm-tools for code coverage (default: false)
-  mkLocalBuilder =
+  #   rustflags: Extra rustc flags, e.g. [ "-C" "target-cpu=x86-64-v3" ]. They are
+  #     merged with the linker and crt-static flags and with the package's own
+  #     rustflags (default: [ ])
+  #   extraEnv: Extra environment variables set on every derivation built with
+  #     this builder, including the dependency build (default: { })
+  #   glibcVersion: Minimum glibc version for dynamic glibc targets (e.g. "2.28").
+  #     Binaries are linked with zig against that glibc release (default: null)
+  #   cxxStdlib: C++ runtime that static Linux builders link statically for
+  #     crates compiling C++ code through cc-rs, or null to keep the crates'
+  #     choice (default: "stdc++")
+  #   linker: Linker backend, one of "mold", "lld", "bfd" or "wild". Fails
+  #     evaluation when the target can't use it. The linker passed to
+  #     mkRustBuilders only applies to the targets that support it
+  #     (default: null)
+  mkTargetBuilder =
     {
-      useRustNightly ? false,
+      crossSystem ? null,
+      isStatic ? false,
+      channel ? null,
       withLlvmTools ? false,
+      rustflags ? [ ],
+      extraEnv ? { },
+      glibcVersion ? null,
+      linker ? null,
+      cxxStdlib ? "stdc++",
     }:
+    let
+      targetChannel = if channel == null then defaultChannel else channel;
+      isWasm = builtins.isString crossSystem && lib.hasPrefix "wasm32-" crossSystem;
+      targetSystem =
+        if crossSystem == null || isWasm then
+          localSystem
+        else if builtins.isString crossSystem then
+          { config = crossSystem; }
+        else
+          crossSystem;
+      isNative = lib.systems.equals (lib.systems.elaborate localSystem) (
+        lib.systems.elaborate targetSystem
+      );
+    in
     import ./rust-builder.nix {
       inherit
         nixpkgs
@@ -35,10 +98,69 @@ rec {
         rust-overlay
         crane
         localSystem
-        useRustNightly
+        isStatic
         withLlvmTools
+        rustflags
+        extraEnv
         rustToolchainFile
+        glibcVersion
+        nightlyDate
+        cxxStdlib
         ;
+      crossSystem = targetSystem;
+      # zig links against its own glibc stubs, so even same-platform glibc
+      # builds behave like cross builds
+      isCross = isWasm || glibcVersion != null || !isNative;
+      inherit linker defaultLinker;
+      useRustNightly = targetChannel == "nightly";
+      channel = if targetChannel == "nightly" then defaultChannel else targetChannel;
+      rustVersion = if targetChannel == defaultChannel then rustVersion else null;
+      rustTarget = if isWasm then crossSystem else null;
+    };
+
+  # Create builders for a list of declarative targets
+  # Only the requested targets are returned, keyed by their (unique) name.
+  #
+  # Arguments:
+  #   targets: List of target descriptions, each an attrset with a `name` plus
+  #     any of the arguments accepted by mkTargetBuilder
+  #
+  # Example:
+  #   mkBuilders [
+  #     { name = "local"; }
+  #     { name = "aarch64-linux"; crossSystem = "aarch64-unknown-linux-musl"; isStatic = true; }
+  #   ]
+  mkBuilders =
+    targets:
+    let
+      names = map (target: target.name) targets;
+      duplicateNames = lib.unique (
+        builtins.filter (name: builtins.length (builtins.filter (other: other == name) names) > 1) names
+      );
+    in
+    assert lib.assertMsg (duplicateNames == [ ])
+      "mkBuilders: duplicate target names ${lib.concatStringsSep ", " duplicateNames}";
+    builtins.listToAttrs (
+      map (target: {
+        inherit (target) name;
+        value = mkTargetBuilder (builtins.removeAttrs target [ "name" ]);
+      }) targets
+    );
+
+  # Create a Rust builder for the local platform
+  # This is the default builder used for development
+  #
+  # Arguments:
+  #   useRustNightly: Whether to use nightly Rust toolchain (default: false)
+  #   withLlvmTools: Whether to include llvm-tools for code coverage (default: false)
+  mkLocalBuilder =
+    {
+      useRustNightly ? false,
+      withLlvmTools ? false,
+    }:
+    mkTargetBuilder {
+      inherit withLlvmTools;
+      channel = if useRustNightly then "nightly" else null;
     };
 
   # Create a Rust builder for x86_64 Linux with musl (static linking)
@@ -47,16 +169,8 @@ rec {
   # Arguments: none
   mkX86_64LinuxBuilder =
     { }:
-    import ./rust-builder.nix {
-      inherit
-        nixpkgs
-        rust-overlay
-        crane
-        localSystem
-        rustToolchainFile
-        ;
-      crossSystem = (import nixpkgs { inherit localSystem; }).lib.systems.examples.musl64;
-      isCross = true;
+    mkTargetBuilder {
+      crossSystem = lib.systems.examples.musl64;
       isStatic = true;
     };
 
@@ -66,42 +180,84 @@ rec {
   # Arguments: none
   mkAarch64LinuxBuilder =
     { }:
-    import ./rust-builder.nix {
-      inherit
-        nixpkgs
-        rust-overlay
-        crane
-        localSystem
-        rustToolchainFile
-        ;
-      crossSystem =
-        (import nixpkgs { inherit localSystem; }).lib.systems.examples.aarch64-multiplatform-musl;
-      isCross = true;
+    mkTargetBuilder {
+      crossSystem = lib.systems.examples.aarch64-multiplatform-musl;
+      isStatic = true;
+    };
+
+  # Create a Rust builder for armv7 Linux with musl (static linking)
+  # Used for 32-bit ARM edge devices (hard-float ABI)
+  #
+  # Arguments: none
+  mkArmv7LinuxBuilder =
+    { }:
+    mkTargetBuilder {
+      crossSystem = armv7MuslSystem;
+      isStatic = true;
+    };
+
+  # Create a Rust builder for riscv64 Linux with musl (static linking)
+  #
+  # Arguments: none
+  mkRiscv64LinuxBuilder =
+    { }:
+    mkTargetBuilder {
+      crossSystem = riscv64MuslSystem;
+      isStatic = true;
+    };
+
+  # Create a Rust builder for i686 Linux with musl (static linking)
+  # Used for legacy 32-bit x86 deployments
+  #
+  # Arguments: none
+  mkI686LinuxBuilder =
+    { }:
+    mkTargetBuilder {
+      crossSystem = lib.systems.examples.musl32;
       isStatic = true;
     };
 
+  # Create a Rust builder for dynamically linked glibc Linux binaries
+  # The binaries require at most the given glibc version, so they run on stock
+  # Debian/Ubuntu hosts. Useful for dependencies that perform poorly on musl.
+  #
+  # Arguments:
+  #   crossSystem: glibc Linux target (default: lib.systems.examples.gnu64)
+  #   glibcVersion: Minimum glibc version to target (default: "2.28")
+  mkGlibcLinuxBuilder =
+    {
+      crossSystem ? lib.systems.examples.gnu64,
+      glibcVersion ? "2.28",
+    }:
+    mkTargetBuilder { inherit crossSystem glibcVersion; };
+
+  # Create a Rust builder for x86_64 Windows using the mingw-w64 toolchain
+  # Tests are run under Wine when building on x86_64 Linux
+  #
+  # Arguments: none
+  mkX86_64WindowsBuilder =
+    { }:
+    mkTargetBuilder { crossSystem = lib.systems.examples.mingwW64; };
+
+  # Create a Rust builder for WebAssembly
+  # Produces .wasm artifacts. WASI targets run their tests with wasmtime.
+  #
+  # Arguments:
+  #   target: Rust wasm target, one of "wasm32-unknown-unknown", "wasm32-wasip1"
+  #     or "wasm32-wasip2" (default: "wasm32-wasip1")
+  mkWasm32Builder =
+    {
+      target ? "wasm32-wasip1",
+    }:
+    mkTargetBuilder { crossSystem = target; };
+
   # Create a Rust builder for x86_64 macOS
   # Note: Must be built from a Darwin system for proper code signing
   #
   # Arguments: none
   mkX86_64DarwinBuilder =
     { }:
-    let
-      pkgsLocal = import nixpkgs { inherit localSystem; };
-      crossSystem = pkgsLocal.lib.systems.examples.x86_64-darwin;
-      isNative = pkgsLocal.lib.systems.equals (pkgsLocal.lib.systems.elaborate localSystem) crossSystem;
-    in
-    import ./rust-builder.nix {
-      inherit
-        nixpkgs
-        rust-overlay
-        crane
-        localSystem
-        rustToolchainFile
-        crossSystem
-        ;
-      isCross = !isNative;
-    };
+    mkTargetBuilder { crossSystem = lib.systems.examples.x86_64-darwin; };
 
   # Create a Rust builder for aarch64 macOS (Apple Silicon)
   # Note: Must be built from a Darwin system for proper code signing
@@ -109,22 +265,7 @@ rec {
   # Arguments: none
   mkAarch64DarwinBuilder =
     { }:
-    let
-      pkgsLocal = import nixpkgs { inherit localSystem; };
-      crossSystem = pkgsLocal.lib.systems.examples.aarch64-darwin;
-      isNative = pkgsLocal.lib.systems.equals (pkgsLocal.lib.systems.elaborate localSystem) crossSystem;
-    in
-    import ./rust-builder.nix {
-      inherit
-        nixpkgs
-        rust-overlay
-        crane
-        localSystem
-        rustToolchainFile
-        crossSystem
-        ;
-      isCross = !isNative;
-    };
+    mkTargetBuilder { crossSystem = lib.systems.examples.aarch64-darwin; };
 
   # Create a Rust builder for the local platform with llvm-tools
   # This builder includes LLVM instrumentation tools required for code coverage
@@ -132,6 +273,79 @@ rec {
   # Arguments: none
   mkCoverageBuilder = { }: mkLocalBuilder { withLlvmTools = true; };
 
+  # Target descriptions for the default builder set returned by mkAllBuilders
+  # Can be filtered or extended and passed to mkBuilders.
+  defaultTargets = [
+    { name = "local"; }
+    {
+      name = "localNightly";
+      channel = "nightly";
+    }
+    {
+      name = "localCoverage";
+      withLlvmTools = true;
+    }
+    {
+      name = "x86_64-linux";
+      crossSystem = lib.systems.examples.musl64;
+      isStatic = true;
+    }
+    {
+      name = "aarch64-linux";
+      crossSystem = lib.systems.examples.aarch64-multiplatform-musl;
+      isStatic = true;
+    }
+    {
+      name = "x86_64-linux-gnu";
+      crossSystem = lib.systems.examples.gnu64;
+      glibcVersion = "2.28";
+    }
+    {
+      name = "aarch64-linux-gnu";
+      crossSystem = lib.systems.examples.aarch64-multiplatform;
+      glibcVersion = "2.28";
+    }
+    {
+      name = "armv7l-linux";
+      crossSystem = armv7MuslSystem;
+      isStatic = true;
+    }
+    {
+      name = "riscv64-linux";
+      crossSystem = riscv64MuslSystem;
+      isStatic = true;
+    }
+    {
+      name = "i686-linux";
+      crossSystem = lib.systems.examples.musl32;
+      isStatic = true;
+    }
+    {
+      name = "x86_64-windows";
+      crossSystem = lib.systems.examples.mingwW64;
+    }
+    {
+      name = "wasm32-unknown";
+      crossSystem = "wasm32-unknown-unknown";
+    }
+    {
+      name = "wasm32-wasip1";
+      crossSystem = "wasm32-wasip1";
+    }
+    {
+      name = "wasm32-wasip2";
+      crossSystem = "wasm32-wasip2";
+    }
+    {
+      name = "x86_64-darwin";
+      crossSystem = lib.systems.examples.x86_64-darwin;
+    }
+    {
+      name = "aarch64-darwin";
+      crossSystem = lib.systems.examples.aarch64-darwin;
+    }
+  ];
+
   # Helper function to create all platform builders at once
   # Returns an attribute set with all available builders
   #
@@ -147,18 +361,17 @@ rec {
   #     localCoverage: Local platform builder with llvm-tools for code coverage
   #     x86_64-linux: x86_64 Linux static builder
   #     aarch64-linux: aarch64 Linux static builder
+  #     x86_64-linux-gnu: x86_64 Linux glibc (>= 2.28) builder
+  #     aarch64-linux-gnu: aarch64 Linux glibc (>= 2.28) builder
+  #     armv7l-linux: armv7 (hard-float) Linux static builder
+  #     riscv64-linux: riscv64 Linux static builder
+  #     i686-linux: i686 Linux static builder
+  #     x86_64-windows: x86_64 Windows (mingw-w64) builder
+  #     wasm32-unknown: wasm32-unknown-unknown builder
+  #     wasm32-wasip1: WASI preview 1 builder
+  #     wasm32-wasip2: WASI preview 2 builder
   #     x86_64-darwin: x86_64 macOS builder
   #     aarch64-darwin: aarch64 macOS builder
   #   }
-  mkAllBuilders =
-    { }:
-    {
-      local = mkLocalBuilder { };
-      localNightly = mkLocalBuilder { useRustNightly = true; };
-      localCoverage = mkCoverageBuilder { };
-      x86_64-linux = mkX86_64LinuxBuilder { };
-      aarch64-linux = mkAarch64LinuxBuilder { };
-      x86_64-darwin = mkX86_64DarwinBuilder { };
-      aarch64-darwin = mkAarch64DarwinBuilder { };
-    };
+  mkAllBuilders = { }: mkBuilders defaultTargets;
 }
@@ -7,23 +7,37 @@
 #   builder.callPackage lib.mkRustLibrary { ... }
 
 {
+  mode ? null, # Build mode: build, test, clippy or doc (default: build)
   CARGO_PROFILE ? "release", # Cargo build profile (release/dev/etc)
   cargoExtraArgs ? "", # Additional arguments for cargo build
+  features ? [ ], # Cargo features to enable, for the dependency and the main build
+  noDefaultFeatures ? false, # Whether to disable the default features
+  allFeatures ? false, # Whether to enable all features
   cargoToml, # Path to the Cargo.toml file
+  cargoArtifacts ? null, # Prebuilt dependency artifacts, e.g. shared by a workspace (default: built from depsSrc)
   craneLib, # Crane library for Rust builds
   depsSrc, # Source tree with only dependencies
   isCross ? false, # Whether this is cross-compilation
   isStatic ? false, # Whether to create static binaries
+  isWasm ? false, # Whether this targets WebAssembly (set by the builder)
+  linker ? null, # Linker backend used by the builder: mold, lld, bfd or wild (set by the builder)
+  rustTarget ? null, # Rust target triple (set by the builder)
+  buildRustTarget ? null, # Rust triple of the build platform (set by the builder)
+  nativeLibs ? [ ], # Native C libraries used by *-sys crates, e.g. [ "zlib" "sqlite" ]
+  withBindgen ? false, # Whether to provide libclang and header paths for bindgen
+  withProtoc ? false, # Whether to provide protoc for prost/tonic code generation
   lib, # Nixpkgs lib utilities
   libiconv, # Character encoding library
   makeSetupHook, # Nix setup hook creator
   mold, # Fast linker for Rust
+  lld, # LLVM linker
   llvmPackages, # LLVM toolchain packages
   pkg-config, # Package configuration tool
   pkgs, # Nixpkgs package set
   rev ? "unknown", # Git revision for version tracking
-  runClippy ? false, # Whether to run Clippy linter
-  runTests ? false, # Whether to run tests
+  rustflags ? [ ], # Extra rustc flags, appended to the flags set by the builder
+  runClippy ? false, # Whether to run Clippy linter (legacy, same as mode = "clippy")
+  runTests ? false, # Whether to run tests (legacy, same as mode = "test")
   src, # Source tree
   stdenv, # Standard environment
   extraBuildInputs ? [ ], # Additional build inputs
@@ -49,13 +63,34 @@ let
   # Cargo uses underscores in artifact filenames even when the crate name uses hyphens
   pnameUnderscore = builtins.replaceStrings [ "-" ] [ "_" ] pname;
 
+  # mkRustPackage modes that only produce reports. They don't need an
+  # installable binary, so mkRustPackage runs them for library crates too.
+  packageOnlyModes = [
+    "nextest"
+    "nextest-archive"
+    "bench"
+    "bench-compile"
+    "coverage"
+    "features"
+    "sqlx"
+  ];
+
+  # Same mode names as mkRustPackage, libraries support a subset of them
+  actualMode = import ./build-mode.nix {
+    inherit lib mode;
+    caller = "mkRustLibrary";
+    supportedModes = builtins.attrNames modeArgs;
+    flags = { inherit runTests runClippy; };
+  };
+
   actualCargoProfile =
-    if runTests then
-      "test"
-    else if runClippy then
-      "dev"
-    else
-      CARGO_PROFILE;
+    {
+      build = CARGO_PROFILE;
+      test = "test";
+      clippy = "dev";
+      doc = "dev";
+    }
+    .${actualMode};
   pnameSuffix = if actualCargoProfile == "release" then "" else "-${actualCargoProfile}";
   pnameDeps = if actualCargoProfile == "release" then pname else "${pname}-${actualCargoProfile}";
 
@@ -66,14 +101,15 @@ let
   isDarwinForDarwin = buildPlatform.isDarwin && hostPlatform.isDarwin;
   isDarwinForNonDarwin = buildPlatform.isDarwin && !hostPlatform.isDarwin;
 
-  linuxNativeBuildInputs =
-    if buildPlatform.isLinux then
-      [
-        # mold is only supported on Linux
-        mold
-      ]
-    else
-      [ ];
+  # The linker selected by the builder must be available on the build platform.
+  # bfd ships with the C toolchain, wild is driven through clang.
+  linkerNativeBuildInputs =
+    lib.optionals (linker == "mold") [ mold ]
+    ++ lib.optionals (linker == "lld") [ lld ]
+    ++ lib.optionals (linker == "wild") [
+      pkgs.pkgsBuildHost.wild
+      pkgs.pkgsBuildHost.clang
+    ];
   darwinBuildInputs =
     if isDarwinForDarwin || isDarwinForNonDarwin then
       [
@@ -94,8 +130,11 @@ let
     else
       [ ];
 
+  # wasm targets don't link against native C libraries
   buildInputs =
-    if isStatic then
+    if isWasm then
+      [ ]
+    else if isStatic then
       with pkgs.pkgsStatic;
       [
         openssl
@@ -108,6 +147,49 @@ let
         cacert
       ];
 
+  # C libraries requested through nativeLibs, for the target and (when
+  # cross-compiling) for build scripts and proc-macros on the build platform
+  nativeLibsArgs = import ./native-libs.nix {
+    inherit
+      lib
+      pkgs
+      nativeLibs
+      isCross
+      isStatic
+      isWasm
+      rustTarget
+      buildRustTarget
+      ;
+  };
+
+  # Crates running bindgen in their build script need libclang and the C
+  # headers of the platform they generate bindings for
+  bindgenNativeBuildInputs = lib.optionals withBindgen [
+    (import ./bindgen.nix {
+      inherit
+        pkgs
+        makeSetupHook
+        rustTarget
+        buildRustTarget
+        ;
+    })
+  ];
+
+  # prost/tonic build scripts run protoc on the build platform, also when
+  # cross-compiling, and need the well-known types shipped with it
+  protoc = pkgs.pkgsBuildHost.protobuf;
+  protocNativeBuildInputs = lib.optionals withProtoc [ protoc ];
+
+  # The Rust standard library for the mingw-w64 targets links against winpthreads
+  windowsBuildInputs = if hostPlatform.isWindows then [ pkgs.windows.pthreads ] else [ ];
+
+  # Feature selection, passed to the dependency build and the main build alike
+  featureArgs = lib.concatStringsSep " " (
+    lib.optional noDefaultFeatures "--no-default-features"
+    ++ lib.optional allFeatures "--all-features"
+    ++ lib.optional (features != [ ]) "--features ${lib.concatStringsSep "," features}"
+  );
+
   sharedArgsBase = {
     inherit pname pnameSuffix version;
     CARGO_PROFILE = actualCargoProfile;
@@ -119,52 +201,86 @@ let
     ]
     ++ stdenv.extraNativeBuildInputs
     ++ darwinNativeBuildInputs
-    ++ linuxNativeBuildInputs
+    ++ linkerNativeBuildInputs
     ++ crossNativeBuildInputs
+    ++ nativeLibsArgs.nativeBuildInputs
+    ++ bindgenNativeBuildInputs
+    ++ protocNativeBuildInputs
     ++ extraNativeBuildInputs;
-    buildInputs = buildInputs ++ stdenv.extraBuildInputs ++ darwinBuildInputs ++ extraBuildInputs;
+    buildInputs =
+      buildInputs
+      ++ stdenv.extraBuildInputs
+      ++ darwinBuildInputs
+      ++ windowsBuildInputs
+      ++ nativeLibsArgs.buildInputs
+      ++ extraBuildInputs;
 
     # Build only the lib target for this crate
-    cargoExtraArgs = "-p ${pname} --lib ${cargoExtraArgs}";
+    cargoExtraArgs = "-p ${pname} --lib ${featureArgs} ${cargoExtraArgs}";
     strictDeps = true;
     doCheck = false;
     VERGEN_GIT_SHA = rev;
+  }
+  // nativeLibsArgs.env
+  // lib.optionalAttrs withProtoc {
+    PROTOC = "${protoc}/bin/protoc";
+    PROTOC_INCLUDE = "${protoc}/include";
+  }
+  // lib.optionalAttrs (rustflags != [ ]) {
+    # appended to the builder's linker and crt-static flags in callPackage
+    packageRustflags = lib.concatStringsSep " " rustflags;
   };
 
-  sharedArgs =
-    if runTests then
-      sharedArgsBase
-      // {
-        cargoTestExtraArgs = "--workspace";
-        doCheck = true;
-        LD_LIBRARY_PATH = lib.makeLibraryPath [ pkgs.pkgsBuildHost.openssl ];
-        RUST_BACKTRACE = "full";
-      }
-    else if runClippy then
-      sharedArgsBase // { cargoClippyExtraArgs = "-- -Dwarnings"; }
-    else
-      sharedArgsBase;
+  # Mode-specific arguments, the attribute names are the supported modes
+  modeArgs = {
+    build = { };
+    test = {
+      doCheck = true;
+      LD_LIBRARY_PATH = lib.makeLibraryPath [ pkgs.pkgsBuildHost.openssl ];
+      RUST_BACKTRACE = "full";
+    };
+    clippy = {
+      cargoClippyExtraArgs = "-- -Dwarnings";
+    };
+    doc = {
+      cargoDocExtraArgs = "--no-deps";
+    };
+  };
+
+  sharedArgs = sharedArgsBase // modeArgs.${actualMode};
 
   defaultArgs = {
-    cargoArtifacts = craneLib.buildDepsOnly (
-      sharedArgs
-      // {
-        pname = pnameDeps;
-        src = depsSrc;
-      }
-    );
+    cargoArtifacts =
+      if cargoArtifacts != null then
+        cargoArtifacts
+      else
+        craneLib.buildDepsOnly (
+          sharedArgs
+          // {
+            pname = pnameDeps;
+            src = depsSrc;
+          }
+        );
   };
 
   args = sharedArgs // defaultArgs;
 
   builder =
-    if runTests then
-      craneLib.cargoTest
-    else if runClippy then
-      craneLib.cargoClippy
-    else
-      craneLib.buildPackage;
+    {
+      build = craneLib.buildPackage;
+      test = craneLib.cargoTest;
+      clippy = craneLib.cargoClippy;
+      doc = craneLib.cargoDoc;
+    }
+    .${actualMode};
 in
+assert lib.assertMsg (!(builtins.elem mode packageOnlyModes))
+  "mkRustLibrary: mode '${mode}' doesn't install library artifacts, use mkRustPackage with the library's Cargo.toml instead";
+assert lib.assertMsg (!allFeatures || (features == [ ] && !noDefaultFeatures))
+  "mkRustLibrary: allFeatures can't be combined with features or noDefaultFeatures";
+# Package rustflags are only passed to cargo by the builder's callPackage
+assert lib.assertMsg (rustflags == [ ] || rustTarget != null)
+  "mkRustLibrary: rustflags are applied by builder.callPackage, build the package with a builder";
 builder (
   args
   // {
@@ -174,16 +290,20 @@ builder (
       # respect the amount of available cores for building
       export CARGO_BUILD_JOBS=$NIX_BUILD_CORES
     '';
-
+  }
+  # The doc mode keeps cargoDoc's installation of the documentation
+  // lib.optionalAttrs (actualMode != "doc") {
     # Library crates don't produce executables, so `cargo install` would fail.
-    # Instead, copy the compiled .rlib and .a artifacts to $out/lib/.
+    # Instead, copy the compiled .rlib and .a artifacts (and .wasm modules for
+    # wasm targets) to $out/lib/.
     #
     # Cargo uses underscores in artifact filenames regardless of the crate name
     # (e.g. crate "my-lib" produces "libmy_lib-<hash>.rlib").
     installPhaseCommand = ''
       mkdir -p $out/lib
       find target -type f \
-        \( -name "lib${pnameUnderscore}*.rlib" -o -name "lib${pnameUnderscore}*.a" \) \
+        \( -name "lib${pnameUnderscore}*.rlib" -o -name "lib${pnameUnderscore}*.a" \
+          -o -name "${pnameUnderscore}.wasm" \) \
         ! -path "*/incremental/*" \
         -exec cp -n {} "$out/lib/" \;
     '';
@@ -7,34 +7,64 @@
 # with various configurations and profiles.
 
 {
-  buildDocs ? false, # Whether to build documentation
+  mode ? null, # Build mode: build, test, nextest, nextest-archive, clippy, doc, bench, bench-compile, coverage, features or sqlx (default: build)
+  buildDocs ? false, # Whether to build documentation (legacy, same as mode = "doc")
   CARGO_PROFILE ? "release", # Cargo build profile (release/dev/etc)
   cargoExtraArgs ? "", # Additional arguments for cargo build
-  cargoTestExtraArgs ? "--workspace", # Additional arguments for cargo test (before --)
+  features ? [ ], # Cargo features to enable, for the dependency and the main build
+  noDefaultFeatures ? false, # Whether to disable the default features
+  allFeatures ? false, # Whether to enable all features
+  cargoTestExtraArgs ? "--workspace", # Additional arguments for cargo test and cargo nextest run (before --)
+  cargoDocExtraArgs ? "--workspace --no-deps", # Additional arguments for cargo doc
   prependPackageName ? true, # When true, prepend -p ${pname} to cargoExtraArgs
   cargoToml, # Path to the Cargo.toml file
+  pname ? null, # Package name (default: the name in cargoToml)
+  cargoArtifacts ? null, # Prebuilt dependency artifacts, e.g. shared by a workspace (default: built from depsSrc)
   craneLib, # Crane library for Rust builds
   depsSrc, # Source tree with only dependencies
   html-tidy, # HTML validation tool
   isCross ? false, # Whether this is cross-compilation
   isStatic ? false, # Whether to create static binaries
+  isWasm ? false, # Whether this targets WebAssembly (set by the builder)
+  linker ? null, # Linker backend used by the builder: mold, lld, bfd or wild (set by the builder)
+  rustTarget ? null, # Rust target triple (set by the builder)
+  buildRustTarget ? null, # Rust triple of the build platform (set by the builder)
+  nativeLibs ? [ ], # Native C libraries used by *-sys crates, e.g. [ "zlib" "sqlite" ]
+  withBindgen ? false, # Whether to provide libclang and header paths for bindgen
+  withProtoc ? false, # Whether to provide protoc for prost/tonic code generation
+  glibcVersion ? null, # Newest glibc version binaries may require (set by the builder)
   lib, # Nixpkgs lib utilities
   libiconv, # Character encoding library
   makeSetupHook, # Nix setup hook creator
   mold, # Fast linker for Rust
+  lld, # LLVM linker
   llvmPackages, # LLVM toolchain packages
   pandoc, # Universal document converter
   pkg-config, # Package configuration tool
   pkgs, # Nixpkgs package set
   postInstall ? null, # Optional post-install script
+  installCheckCommand ? null, # Optional script run against $out after install ($TARGET_RUNNER runs cross binaries)
   rev ? "unknown", # Git revision for version tracking
-  runClippy ? false, # Whether to run Clippy linter
-  runCoverage ? false, # Whether to run code coverage
-  runTests ? false, # Whether to run tests
-  runBench ? false, # Whether to run benchmarks
-  buildBench ? false, # Whether to compile benchmarks without running (--no-run)
+  rustflags ? [ ], # Extra rustc flags, appended to the flags set by the builder
+  runClippy ? false, # Whether to run Clippy linter (legacy, same as mode = "clippy")
+  runCoverage ? false, # Whether to run code coverage (legacy, same as mode = "coverage")
+  runTests ? false, # Whether to run tests (legacy, same as mode = "test")
+  runBench ? false, # Whether to run benchmarks (legacy, same as mode = "bench")
+  buildBench ? false, # Whether to compile benchmarks without running (legacy, same as mode = "bench-compile")
   cargoLlvmCovExtraArgs ? "--lcov --output-path $out", # Extra args for cargo-llvm-cov
   cargoLlvmCovCommand ? "test", # Subcommand for cargo-llvm-cov (test, run, etc.)
+  cargoHackExtraArgs ? "--feature-powerset", # Feature combinations checked by cargo-hack (e.g. --each-feature)
+  nextestProfile ? "default", # nextest profile used in nextest mode
+  nextestConfigFile ? null, # nextest config file, relative to the source root (default: .config/nextest.toml)
+  nextestAllowFailures ? false, # Whether failing tests still produce $out (see $out/exit-code)
+  nextestArchive ? null, # nextest-archive derivation to run the tests from, instead of compiling them
+  nextestPartition ? null, # nextest partition to run, e.g. "count:1/4"
+  nextestRetries ? 0, # How often failing tests are retried in nextest mode
+  quarantinedTests ? [ ], # Tests whose failures are reported in $out instead of failing the build (nextest mode)
+  testServices ? { }, # Services started for the tests, e.g. { postgres = { }; redis = { }; }
+  sqlxMigrations ? "migrations", # Migrations applied in sqlx mode, relative to the source root
+  sqlxPrepareExtraArgs ? "", # Additional arguments for cargo sqlx prepare (e.g. --workspace)
+  sqlxCli ? null, # sqlx-cli package used in sqlx mode, matching the crate's sqlx version (default: sqlx-cli)
   src, # Source tree
   stdenv, # Standard environment
   extraBuildInputs ? [ ], # Additional build inputs
@@ -48,13 +78,39 @@ let
 
   # The target interpreter is used to patch the interpreter in the binary
   targetInterpreter =
-    if hostPlatform.isLinux && hostPlatform.isx86_64 then
+    if hostPlatform.isLinux && hostPlatform.isMusl then
+      if hostPlatform.isx86_64 then
+        "/lib/ld-musl-x86_64.so.1"
+      else if hostPlatform.isAarch64 then
+        "/lib/ld-musl-aarch64.so.1"
+      else if hostPlatform.isAarch32 then
+        "/lib/ld-musl-armhf.so.1"
+      else if hostPlatform.isRiscV64 then
+        "/lib/ld-musl-riscv64.so.1"
+      else if hostPlatform.isx86_32 then
+        "/lib/ld-musl-i386.so.1"
+      else
+        ""
+    else if hostPlatform.isLinux && hostPlatform.isx86_64 then
       "/lib64/ld-linux-x86-64.so.2"
     else if hostPlatform.isLinux && hostPlatform.isAarch64 then
       "/lib64/ld-linux-aarch64.so.1"
+    else if hostPlatform.isLinux && hostPlatform.isAarch32 then
+      "/lib/ld-linux-armhf.so.3"
+    else if hostPlatform.isLinux && hostPlatform.isRiscV64 then
+      "/lib/ld-linux-riscv64-lp64d.so.1"
+    else if hostPlatform.isLinux && hostPlatform.isx86_32 then
+      "/lib/ld-linux.so.2"
     else
       "";
 
+  # zig already links glibc targets with the standard loader path
+  patchInterpreter =
+    isCross && targetInterpreter != "" && !isStatic && !isWasm && glibcVersion == null;
+
+  # binutils' readelf handles ELF files of any architecture
+  readelf = "${pkgs.pkgsBuildBuild.binutils-unwrapped}/bin/readelf";
+
   # The hook is used when building on darwin for non-darwin, where the flags
   # need to be cleaned up.
   darwinSuffixSalt = builtins.replaceStrings [ "-" "." ] [ "_" "_" ] buildPlatform.config;
@@ -65,22 +121,48 @@ let
   } ./setup-hook-darwin.sh;
 
   crateInfo = craneLib.crateNameFromCargoToml { inherit cargoToml; };
-  pname = crateInfo.pname;
+  actualPname = if pname != null then pname else crateInfo.pname;
+
+  # The legacy flags (runTests, runClippy, ...) are still accepted, but may
+  # not select a different mode than `mode` or each other.
+  actualMode = import ./build-mode.nix {
+    inherit lib mode;
+    caller = "mkRustPackage";
+    supportedModes = builtins.attrNames modeArgs;
+    flags = {
+      inherit
+        runTests
+        runClippy
+        buildDocs
+        runBench
+        buildBench
+        runCoverage
+        ;
+    };
+  };
+
   actualCargoProfile =
-    if runCoverage then
-      "test"
-    else if runTests then
-      "test"
-    else if runClippy then
-      "dev"
-    else if buildDocs then
-      "dev"
-    else if runBench || buildBench then
-      "bench"
-    else
-      CARGO_PROFILE;
+    {
+      build = CARGO_PROFILE;
+      test = "test";
+      nextest = "test";
+      nextest-archive = "test";
+      clippy = "dev";
+      doc = "dev";
+      bench = "bench";
+      bench-compile = "bench";
+      coverage = "test";
+      features = "dev";
+      sqlx = "dev";
+    }
+    .${actualMode};
+  isBuildMode = actualMode == "build";
   pnameSuffix = if actualCargoProfile == "release" then "" else "-${actualCargoProfile}";
-  pnameDeps = if actualCargoProfile == "release" then pname else "${pname}-${actualCargoProfile}";
+  pnameDeps =
+    if actualCargoProfile == "release" then
+      actualPname
+    else
+      "${actualPname}-${actualCargoProfile}";
 
   version = lib.strings.concatStringsSep "." (
     lib.lists.take 3 (builtins.splitVersion crateInfo.version)
@@ -89,14 +171,15 @@ let
   isDarwinForDarwin = buildPlatform.isDarwin && hostPlatform.isDarwin;
   isDarwinForNonDarwin = buildPlatform.isDarwin && !hostPlatform.isDarwin;
 
-  linuxNativeBuildInputs =
-    if buildPlatform.isLinux then
-      [
-        # mold is only supported on Linux
-        mold
-      ]
-    else
-      [ ];
+  # The linker selected by the builder must be available on the build platform.
+  # bfd ships with the C toolchain, wild is driven through clang.
+  linkerNativeBuildInputs =
+    lib.optionals (linker == "mold") [ mold ]
+    ++ lib.optionals (linker == "lld") [ lld ]
+    ++ lib.optionals (linker == "wild") [
+      pkgs.pkgsBuildHost.wild
+      pkgs.pkgsBuildHost.clang
+    ];
   darwinBuildInputs =
     if isDarwinForDarwin || isDarwinForNonDarwin then
       [
@@ -119,8 +202,11 @@ let
     else
       [ ];
 
+  # wasm targets don't link against native C libraries
   buildInputs =
-    if isStatic then
+    if isWasm then
+      [ ]
+    else if isStatic then
       with pkgs.pkgsStatic;
       [
         openssl
@@ -133,10 +219,56 @@ let
         cacert
       ];
 
+  # C libraries requested through nativeLibs, for the target and (when
+  # cross-compiling) for build scripts and proc-macros on the build platform
+  nativeLibsArgs = import ./native-libs.nix {
+    inherit
+      lib
+      pkgs
+      nativeLibs
+      isCross
+      isStatic
+      isWasm
+      rustTarget
+      buildRustTarget
+      ;
+  };
+
+  # Crates running bindgen in their build script need libclang and the C
+  # headers of the platform they generate bindings for
+  bindgenNativeBuildInputs = lib.optionals withBindgen [
+    (import ./bindgen.nix {
+      inherit
+        pkgs
+        makeSetupHook
+        rustTarget
+        buildRustTarget
+        ;
+    })
+  ];
+
+  # prost/tonic build scripts run protoc on the build platform, also when
+  # cross-compiling, and need the well-known types shipped with it
+  protoc = pkgs.pkgsBuildHost.protobuf;
+  protocNativeBuildInputs = lib.optionals withProtoc [ protoc ];
+
+  # The Rust standard library for the mingw-w64 targets links against winpthreads
+  windowsBuildInputs = if hostPlatform.isWindows then [ pkgs.windows.pthreads ] else [ ];
+  windowsRuntimeDeps =
+    buildInputs ++ windowsBuildInputs ++ nativeLibsArgs.buildInputs ++ extraBuildInputs;
+
   opensslLibPath = lib.makeLibraryPath [ pkgs.pkgsBuildHost.openssl ];
 
+  # Feature selection, passed to the dependency build and the main build alike
+  featureArgs = lib.concatStringsSep " " (
+    lib.optional noDefaultFeatures "--no-default-features"
+    ++ lib.optional allFeatures "--all-features"
+    ++ lib.optional (features != [ ]) "--features ${lib.concatStringsSep "," features}"
+  );
+
   sharedArgsBase = {
-    inherit pname pnameSuffix version;
+    inherit pnameSuffix version;
+    pname = actualPname;
     CARGO_PROFILE = actualCargoProfile;
 
     nativeBuildInputs = [
@@ -146,56 +278,110 @@ let
     ]
     ++ stdenv.extraNativeBuildInputs
     ++ darwinNativeBuildInputs
-    ++ linuxNativeBuildInputs
+    ++ linkerNativeBuildInputs
     ++ crossNativeBuildInputs
+    ++ nativeLibsArgs.nativeBuildInputs
+    ++ bindgenNativeBuildInputs
+    ++ protocNativeBuildInputs
     ++ extraNativeBuildInputs;
-    buildInputs = buildInputs ++ stdenv.extraBuildInputs ++ darwinBuildInputs ++ extraBuildInputs;
+    buildInputs =
+      buildInputs
+      ++ stdenv.extraBuildInputs
+      ++ darwinBuildInputs
+      ++ windowsBuildInputs
+      ++ nativeLibsArgs.buildInputs
+      ++ extraBuildInputs;
 
     cargoExtraArgs =
-      if runCoverage then
-        "--workspace ${cargoExtraArgs}"
+      if actualMode == "coverage" then
+        "--workspace ${featureArgs} ${cargoExtraArgs}"
       else if prependPackageName then
-        "-p ${pname} ${cargoExtraArgs}"
+        "-p ${actualPname} ${featureArgs} ${cargoExtraArgs}"
       else
-        cargoExtraArgs;
+        "${featureArgs} ${cargoExtraArgs}";
     strictDeps = true;
     # disable running tests automatically for now
     doCheck = false;
     # set to the revision because during build the Git info is not available
     VERGEN_GIT_SHA = rev;
+  }
+  // nativeLibsArgs.env
+  // lib.optionalAttrs withProtoc {
+    PROTOC = "${protoc}/bin/protoc";
+    PROTOC_INCLUDE = "${protoc}/include";
+  }
+  // lib.optionalAttrs (rustflags != [ ]) {
+    # appended to the builder's linker and crt-static flags in callPackage
+    packageRustflags = lib.concatStringsSep " " rustflags;
   };
 
-  sharedArgs =
-    if runCoverage then
-      sharedArgsBase
-      // {
-        inherit cargoLlvmCovExtraArgs cargoLlvmCovCommand;
-        LD_LIBRARY_PATH = lib.makeLibraryPath [ pkgs.pkgsBuildHost.openssl ];
-        RUST_BACKTRACE = "full";
-      }
-    else if runTests then
-      sharedArgsBase
-      // {
-        inherit cargoTestExtraArgs;
-        doCheck = true;
-        LD_LIBRARY_PATH = opensslLibPath;
-        RUST_BACKTRACE = "full";
-      }
-    else if runClippy then
-      sharedArgsBase // { cargoClippyExtraArgs = "-- -Dwarnings"; }
-    else if runBench || buildBench then
-      sharedArgsBase
-      // {
-        LD_LIBRARY_PATH = opensslLibPath;
-        RUST_BACKTRACE = "full";
-      }
-    else
-      sharedArgsBase;
+  # Mode-specific arguments, the attribute names are the supported modes
+  benchArgs = {
+    LD_LIBRARY_PATH = opensslLibPath;
+    RUST_BACKTRACE = "full";
+  };
+  modeArgs = {
+    build = { };
+    test = {
+      inherit cargoTestExtraArgs;
+      doCheck = true;
+      LD_LIBRARY_PATH = opensslLibPath;
+      RUST_BACKTRACE = "full";
+    };
+    nextest = {
+      inherit
+        cargoTestExtraArgs
+        nextestProfile
+        nextestConfigFile
+        nextestAllowFailures
+        nextestArchive
+        nextestPartition
+        nextestRetries
+        quarantinedTests
+        ;
+      LD_LIBRARY_PATH = opensslLibPath;
+      RUST_BACKTRACE = "full";
+    };
+    nextest-archive = {
+      inherit cargoTestExtraArgs nextestProfile nextestConfigFile;
+    };
+    clippy = {
+      cargoClippyExtraArgs = "-- -Dwarnings";
+    };
+    doc = { };
+    bench = benchArgs;
+    bench-compile = benchArgs;
+    coverage = {
+      inherit cargoLlvmCovExtraArgs cargoLlvmCovCommand;
+      LD_LIBRARY_PATH = opensslLibPath;
+      RUST_BACKTRACE = "full";
+    };
+    features = {
+      inherit cargoHackExtraArgs;
+    };
+    sqlx = {
+      inherit sqlxMigrations sqlxPrepareExtraArgs;
+    };
+  };
+
+  sharedArgs = sharedArgsBase // modeArgs.${actualMode};
+
+  # nextest settings only affect the test run. They are kept out of the
+  # dependency build, so changing them doesn't rebuild the dependencies.
+  nextestArgNames = [
+    "nextestProfile"
+    "nextestConfigFile"
+    "nextestAllowFailures"
+    "nextestArchive"
+    "nextestPartition"
+    "nextestRetries"
+    "quarantinedTests"
+  ];
 
   docsArgs = {
     cargoArtifacts = null;
-    cargoExtraArgs = ""; # overwrite the default to build all docs
-    cargoDocExtraArgs = "--workspace --no-deps";
+    cargoExtraArgs = featureArgs; # overwrite the default to build all docs
+    inherit cargoDocExtraArgs;
     RUSTDOCFLAGS = "--enable-index-page -Z unstable-options -D warnings --document-private-items";
     CARGO_TARGET_DIR = "target/";
     LD_LIBRARY_PATH = opensslLibPath;
@@ -210,42 +396,139 @@ let
   };
 
   defaultArgs = {
-    cargoArtifacts = craneLib.buildDepsOnly (
-      sharedArgs
-      // {
-        pname = pnameDeps;
-        src = depsSrc;
-        # Override test args for deps: run --lib tests (which are empty stubs)
-        # to ensure all test artifacts including build.rs outputs are generated,
-        # without requiring actual integration test files in the dep source.
-        cargoTestExtraArgs = "--lib";
-      }
-    );
+    cargoArtifacts =
+      if cargoArtifacts != null then
+        cargoArtifacts
+      # Tests run from a nextest archive are already compiled
+      else if actualMode == "nextest" && nextestArchive != null then
+        null
+      else
+        craneLib.buildDepsOnly (
+          builtins.removeAttrs sharedArgs nextestArgNames
+          // {
+            pname = pnameDeps;
+            src = depsSrc;
+            # Override test args for deps: run --lib tests (which are empty stubs)
+            # to ensure all test artifacts including build.rs outputs are generated,
+            # without requiring actual integration test files in the dep source.
+            cargoTestExtraArgs = "--lib";
+          }
+          # Build the dependencies of all optional features once, instead of
+          # in every combination checked by cargo-hack
+          // lib.optionalAttrs (actualMode == "features") {
+            cargoExtraArgs = "${sharedArgs.cargoExtraArgs} --all-features";
+          }
+        );
   };
 
-  args = if buildDocs then sharedArgs // docsArgs else sharedArgs // defaultArgs;
+  args = if actualMode == "doc" then sharedArgs // docsArgs else sharedArgs // defaultArgs;
 
-  mkBench = import ./cargo-bench.nix {
-    mkCargoDerivation = craneLib.mkCargoDerivation;
-    noRun = buildBench;
+  # wasm artifacts are plain .wasm files in the target's profile directory.
+  # WASI modules are runnable and go to $out/bin, wasm32-unknown-unknown
+  # modules are loaded by a host runtime and go to $out/lib.
+  wasmInstallArgs = lib.optionalAttrs (isWasm && isBuildMode) {
+    installPhaseCommand = ''
+      case "$CARGO_BUILD_TARGET" in
+        wasm32-wasi*) wasmOut=$out/bin ;;
+        *) wasmOut=$out/lib ;;
+      esac
+      mkdir -p $wasmOut
+      find target/$CARGO_BUILD_TARGET -maxdepth 2 -type f -name '*.wasm' \
+        -exec cp {} "$wasmOut/" \;
+    '';
   };
 
+  # Install checks run the installed binaries. For cross builds they are run
+  # through the same runner cargo uses for tests (e.g. QEMU), which is exposed
+  # as $TARGET_RUNNER and is empty for native builds.
+  installCheckArgs = lib.optionalAttrs (installCheckCommand != null && isBuildMode) {
+    doInstallCheck = true;
+    installCheckPhase = ''
+      runHook preInstallCheck
+      runnerVar="CARGO_TARGET_$(echo "$CARGO_BUILD_TARGET" | tr 'a-z-' 'A-Z_')_RUNNER"
+      export TARGET_RUNNER="''${!runnerVar:-}"
+      ${installCheckCommand}
+      runHook postInstallCheck
+    '';
+  };
+
+  # Services are started before cargo runs and stopped before the install
+  # phase, in all modes running tests. The sqlx mode always needs PostgreSQL.
+  actualTestServices =
+    if actualMode == "sqlx" then { postgres = { }; } // testServices else testServices;
+  testServicesArgs =
+    let
+      services = import ./test-services.nix {
+        inherit lib pkgs;
+        testServices = actualTestServices;
+      };
+    in
+    lib.optionalAttrs (actualTestServices != { }) {
+      preBuild = services.start;
+      preInstall = services.stop;
+    };
+
+  mkBench =
+    noRun:
+    import ./cargo-bench.nix {
+      mkCargoDerivation = craneLib.mkCargoDerivation;
+      inherit noRun;
+    };
+
+  mkNextest =
+    archiveOnly:
+    import ./cargo-nextest.nix {
+      inherit lib;
+      mkCargoDerivation = craneLib.mkCargoDerivation;
+      cargo-nextest = pkgs.pkgsBuildHost.cargo-nextest;
+      inherit (pkgs) writeText;
+      inherit archiveOnly;
+    };
+
   builder =
-    if runCoverage then
-      craneLib.cargoLlvmCov
-    else if runTests then
-      craneLib.cargoTest
-    else if runClippy then
-      craneLib.cargoClippy
-    else if buildDocs then
-      craneLib.cargoDoc
-    else if runBench || buildBench then
-      mkBench
-    else
-      craneLib.buildPackage;
+    {
+      build = craneLib.buildPackage;
+      test = craneLib.cargoTest;
+      nextest = mkNextest false;
+      nextest-archive = mkNextest true;
+      clippy = craneLib.cargoClippy;
+      doc = craneLib.cargoDoc;
+      bench = mkBench false;
+      bench-compile = mkBench true;
+      coverage = craneLib.cargoLlvmCov;
+      features = import ./cargo-hack.nix {
+        mkCargoDerivation = craneLib.mkCargoDerivation;
+        cargo-hack = pkgs.pkgsBuildHost.cargo-hack;
+      };
+      sqlx = import ./cargo-sqlx.nix {
+        mkCargoDerivation = craneLib.mkCargoDerivation;
+        sqlx-cli = if sqlxCli != null then sqlxCli else pkgs.pkgsBuildHost.sqlx-cli;
+      };
+    }
+    .${actualMode};
 in
+assert lib.assertMsg (!allFeatures || (features == [ ] && !noDefaultFeatures))
+  "mkRustPackage: allFeatures can't be combined with features or noDefaultFeatures";
+# Package rustflags are only passed to cargo by the builder's callPackage
+assert lib.assertMsg (rustflags == [ ] || rustTarget != null)
+  "mkRustPackage: rustflags are applied by builder.callPackage, build the package with a builder";
+assert lib.assertMsg (!allFeatures || actualMode != "features")
+  "mkRustPackage: mode = \"features\" checks feature combinations itself, unset allFeatures";
+assert lib.assertMsg (actualMode == "nextest" || (nextestRetries == 0 && quarantinedTests == [ ]))
+  "mkRustPackage: nextestRetries and quarantinedTests need mode = \"nextest\", which retries and filters single tests";
+assert lib.assertMsg (
+  testServices == { } || builtins.elem actualMode [
+    "test"
+    "nextest"
+    "coverage"
+    "sqlx"
+  ]
+) "mkRustPackage: testServices are only started in the test, nextest, coverage and sqlx modes";
 builder (
   args
+  // wasmInstallArgs
+  // installCheckArgs
+  // testServicesArgs
   // {
     inherit src postInstall;
 
@@ -254,12 +537,38 @@ builder (
       export CARGO_BUILD_JOBS=$NIX_BUILD_CORES
     '';
 
-    preFixup = lib.optionalString (isCross && targetInterpreter != "" && !isStatic) ''
-      for f in `find $out/bin/ -type f`; do
-        echo "patching interpreter for $f to ${targetInterpreter}"
-        patchelf --set-interpreter ${targetInterpreter} --output $f.patched $f
-        mv $f.patched $f
-      done
-    '';
+    preFixup =
+      lib.optionalString patchInterpreter ''
+        for f in `find $out/bin/ -type f`; do
+          echo "patching interpreter for $f to ${targetInterpreter}"
+          patchelf --set-interpreter ${targetInterpreter} --output $f.patched $f
+          mv $f.patched $f
+        done
+      ''
+      + lib.optionalString hostPlatform.isWindows ''
+        # Windows looks up DLLs next to the executable, so ship the runtime
+        # DLLs of the linked libraries alongside the .exe files.
+        if [ -d $out/bin ]; then
+          for dll in `find -L ${lib.concatMapStringsSep " " lib.getBin windowsRuntimeDeps} -name '*.dll' 2>/dev/null`; do
+            echo "installing runtime library $dll"
+            cp -n $dll $out/bin/
+          done
+        fi
+      ''
+      + lib.optionalString (glibcVersion != null && isBuildMode) ''
+        # Reject binaries that reference glibc symbols newer than the targeted
+        # release, e.g. when a C dependency was built against nixpkgs' glibc.
+        # Binaries without versioned glibc symbols make grep fail, which must
+        # not abort the build under pipefail.
+        for f in `find $out/bin/ -type f`; do
+          required=`${readelf} --dyn-syms -W $f | grep -o 'GLIBC_[0-9][0-9.]*' | sed 's/^GLIBC_//' | sort -uV | tail -n1 || true`
+          newest=`printf '%s\n' "$required" "${glibcVersion}" | sort -V | tail -n1`
+          if [ -n "$required" ] && [ "$newest" != "${glibcVersion}" ]; then
+            echo "$f requires glibc $required, but the builder targets glibc ${glibcVersion}:"
+            ${readelf} --dyn-syms -W $f | grep -o '[^ ]*@GLIBC_[0-9][0-9.]*' | sort -u
+            exit 1
+          fi
+        done
+      '';
   }
 )
@@ -0,0 +1,77 @@
+# rust-toolchain.nix - Rust toolchain selection
+#
+# Resolves the Rust toolchain from rust-overlay's `rust-bin` based on a single
+# specification. Builders, development shells, the audit app and the rustfmt
+# formatter all go through this function, so the same specification always
+# results in the same compiler.
+#
+# This is a low-level building block used internally by the library.
+
+{
+  rustToolchainFile ? null, # Optional path to rust-toolchain.toml
+  channel ? "stable", # Toolchain channel: stable, beta or nightly
+  rustVersion ? null, # Pinned stable version (e.g. "1.83.0") or beta date (e.g. "2026-09-01")
+  nightlyDate ? null, # Pinned nightly date (e.g. "2026-09-01") used for nightly toolchains
+}:
+
+let
+  channels = [
+    "stable"
+    "beta"
+    "nightly"
+  ];
+in
+assert
+  builtins.elem channel channels
+  || throw "rust-toolchain: unsupported channel '${channel}', expected one of: stable, beta, nightly";
+assert
+  channel != "nightly"
+  || rustVersion == null
+  || throw "rust-toolchain: use nightlyDate instead of rustVersion to pin a nightly toolchain";
+assert
+  rustToolchainFile == null
+  || (channel == "stable" && rustVersion == null)
+  || throw "rust-toolchain: rustToolchainFile cannot be combined with channel or rustVersion";
+rec {
+  # Whether a toolchain other than the latest stable release was requested
+  isPinned = channel != "stable" || rustVersion != null || nightlyDate != null;
+
+  # Select the toolchain
+  #
+  # Arguments:
+  #   rust-bin: rust-overlay's `rust-bin` attribute of the package set to use
+  #   nightly: Select the nightly toolchain regardless of the channel (default: false)
+  #   targets: Additional Rust targets to include (default: [ ])
+  #   extensions: Additional rustup components to include (default: [ ])
+  select =
+    rust-bin:
+    {
+      nightly ? false,
+      targets ? [ ],
+      extensions ? [ ],
+    }:
+    let
+      override = toolchain: toolchain.override { inherit targets extensions; };
+    in
+    if nightly || channel == "nightly" then
+      if nightlyDate != null then
+        override rust-bin.nightly.${nightlyDate}.default
+      else
+        rust-bin.selectLatestNightlyWith (toolchain: override toolchain.default)
+    else if rustToolchainFile != null then
+      override (rust-bin.fromRustupToolchainFile rustToolchainFile)
+    else
+      override rust-bin.${channel}.${if rustVersion != null then rustVersion else "latest"}.default;
+
+  # Select the toolchain providing rustfmt
+  # The project configuration may use unstable rustfmt options, so the latest
+  # nightly is used unless a toolchain was pinned explicitly.
+  #
+  # Arguments:
+  #   rust-bin: rust-overlay's `rust-bin` attribute of the package set to use
+  selectRustfmt =
+    rust-bin:
+    select rust-bin {
+      nightly = nightlyDate != null || !isPinned;
+    };
+}
@@ -0,0 +1,159 @@
+# rust-workspace.nix - Cargo workspace builder
+#
+# Reads the members of a Cargo workspace and builds one derivation per member
+# crate: binaries with rust-package.nix, libraries with rust-library.nix. The
+# dependencies of the whole workspace are built once (one buildDepsOnly
+# derivation per builder and profile) and shared by all members.
+#
+# This is a low-level building block. Most users should use mkRustWorkspace
+# from default.nix.
+
+{
+  lib, # Nixpkgs lib utilities
+  builder, # Builder used for all members (e.g. builders.local)
+  src, # Source tree of the whole workspace
+  depsSrc, # Dependency-only source tree of the whole workspace
+  cargoToml, # Workspace root Cargo.toml, with Cargo.lock next to it
+  name ? "workspace", # Name of the shared dependency derivation
+  members ? null, # Member crate names to build (default: all members)
+  args ? { }, # Extra arguments for every member (e.g. mode, CARGO_PROFILE, rev)
+  memberArgs ? { }, # Extra arguments per member, keyed by crate name (or a function of the name)
+  package ? null, # Package function for all members (default: by crate kind)
+}:
+let
+  mkRustPackage = import ./rust-package.nix;
+  mkRustLibrary = import ./rust-library.nix;
+
+  root = dirOf cargoToml;
+  manifest = builtins.fromTOML (builtins.readFile cargoToml);
+
+  # Expand a workspace member pattern (e.g. "crates/*") into directories.
+  # Cargo supports glob characters in any path component.
+  globToRegex =
+    pattern:
+    builtins.replaceStrings
+      [
+        "."
+        "*"
+        "?"
+      ]
+      [
+        "\\."
+        ".*"
+        "."
+      ]
+      pattern;
+  expandPattern =
+    dir: components:
+    if components == [ ] then
+      [ dir ]
+    else
+      let
+        component = builtins.head components;
+        rest = builtins.tail components;
+        entries = builtins.readDir dir;
+        matches = builtins.filter (
+          entry:
+          entries.${entry} == "directory" && builtins.match (globToRegex component) entry != null
+        ) (builtins.attrNames entries);
+      in
+      if lib.hasInfix "*" component || lib.hasInfix "?" component then
+        lib.concatMap (entry: expandPattern (dir + "/${entry}") rest) matches
+      else
+        expandPattern (dir + "/${component}") rest;
+
+  excludedDirs = map (path: root + "/${path}") (manifest.workspace.exclude or [ ]);
+  memberDirs =
+    builtins.filter
+      (dir: builtins.pathExists (dir + "/Cargo.toml") && !(builtins.elem dir excludedDirs))
+      (
+        lib.concatMap (pattern: expandPattern root (lib.splitString "/" pattern)) (
+          manifest.workspace.members or [ ]
+        )
+        ++ lib.optional (manifest ? package) root
+      );
+
+  # Crates with a main.rs, src/bin or [[bin]] targets are built as packages,
+  # all others as libraries
+  readMember =
+    dir:
+    let
+      memberManifest = builtins.fromTOML (builtins.readFile (dir + "/Cargo.toml"));
+    in
+    {
+      name = memberManifest.package.name;
+      value = {
+        cargoToml = dir + "/Cargo.toml";
+        isBinary =
+          memberManifest ? bin
+          || builtins.pathExists (dir + "/src/main.rs")
+          || builtins.pathExists (dir + "/src/bin");
+      };
+    };
+  allMembers = builtins.listToAttrs (map readMember memberDirs);
+
+  unknownMembers = builtins.filter (member: !(allMembers ? ${member})) (
+    if members == null then [ ] else members
+  );
+  selectedMembers = if members == null then allMembers else lib.getAttrs members allMembers;
+
+  # Arguments that change how the dependencies are built. The dependencies
+  # are shared by all members, so these can only be set in `args`.
+  depsArgNames = [
+    "CARGO_PROFILE"
+    "features"
+    "noDefaultFeatures"
+    "allFeatures"
+    "nativeLibs"
+    "withBindgen"
+    "withProtoc"
+    "rustflags"
+    "extraBuildInputs"
+    "extraNativeBuildInputs"
+  ];
+
+  # Dependencies of all members, built once with the workspace-wide arguments
+  cargoArtifacts =
+    (builder.callPackage mkRustPackage (
+      args
+      // {
+        inherit src depsSrc;
+        inherit (builtins.head (builtins.attrValues allMembers)) cargoToml;
+        pname = name;
+        prependPackageName = false;
+        cargoExtraArgs = "--workspace ${args.cargoExtraArgs or ""}";
+      }
+    )).cargoArtifacts;
+
+  mkMember =
+    memberName: member:
+    let
+      memberPackage =
+        if package != null then
+          package
+        else if member.isBinary then
+          mkRustPackage
+        else
+          mkRustLibrary;
+      extraArgs =
+        if builtins.isFunction memberArgs then memberArgs memberName else memberArgs.${memberName} or { };
+      depsArgs = builtins.filter (argName: extraArgs ? ${argName}) depsArgNames;
+    in
+    assert lib.assertMsg (depsArgs == [ ])
+      "mkRustWorkspace: ${lib.concatStringsSep ", " depsArgs} in memberArgs.${memberName} would not reach the shared dependency build, set them in args instead";
+    builder.callPackage memberPackage (
+      args
+      // {
+        inherit src depsSrc cargoArtifacts;
+        inherit (member) cargoToml;
+      }
+      // extraArgs
+    );
+in
+assert lib.assertMsg (builtins.pathExists (root + "/Cargo.lock"))
+  "mkRustWorkspace: ${toString cargoToml} has no Cargo.lock next to it";
+assert lib.assertMsg (allMembers != { })
+  "mkRustWorkspace: no member crates found in ${toString cargoToml}";
+assert lib.assertMsg (unknownMembers == [ ])
+  "mkRustWorkspace: unknown members ${lib.concatStringsSep ", " unknownMembers}, the workspace has: ${lib.concatStringsSep ", " (builtins.attrNames allMembers)}";
+lib.mapAttrs mkMember selectedMembers
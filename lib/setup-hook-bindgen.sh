@@ -0,0 +1,26 @@
+# Point bindgen at libclang and at the headers of the target and of the build
+# platform, using the include paths the cc wrappers pass to the C compiler.
+# The clang arguments are scoped per Rust target, so build scripts of both
+# platforms see the right headers when cross-compiling.
+bindgenCcFlags() {
+  local cc=$1 file flags=""
+  for file in cc-cflags libc-cflags libcxx-cxxflags; do
+    if [ -f "$cc/nix-support/$file" ]; then
+      flags+=" $(<"$cc/nix-support/$file")"
+    fi
+  done
+  echo "$flags"
+}
+
+populateBindgenEnv() {
+  export LIBCLANG_PATH=@libclang@/lib
+
+  # NIX_CFLAGS_COMPILE carries the include paths of the target buildInputs
+  # (e.g. nativeLibs), NIX_CFLAGS_COMPILE_FOR_BUILD those of the build platform.
+  export @targetVar@="-resource-dir=@clang@/resource-root $(bindgenCcFlags @targetCc@) ${NIX_CFLAGS_COMPILE-}"
+  if [ "@buildVar@" != "@targetVar@" ]; then
+    export @buildVar@="-resource-dir=@clang@/resource-root $(bindgenCcFlags @buildCc@) ${NIX_CFLAGS_COMPILE_FOR_BUILD-}"
+  fi
+}
+
+postHook="${postHook:-}"$'\n'"populateBindgenEnv"$'\n'
@@ -11,6 +11,9 @@
   ciTools,
   rustToolchain ? null, # Optional Rust toolchain override
   rustToolchainFile ? null, # Optional path to rust-toolchain.toml
+  channel ? "stable", # Toolchain channel: stable, beta or nightly
+  rustVersion ? null, # Pinned stable version (e.g. "1.83.0") or beta date
+  nightlyDate ? null, # Pinned nightly date (e.g. "2026-09-01")
   extraPackages ? [ ], # Additional packages (take PATH precedence over defaults)
   shellName ? "Development", # Name shown in shell prompt
   shellHook ? "", # Additional shell hook commands
@@ -20,6 +23,7 @@
   postgresPackage ? null, # Optional PostgreSQL package override
   withLlvmTools ? false, # Whether to include llvm-tools for code coverage
   includeCiPackages ? true, # Whether to include CI/CD tooling
+  withProtoc ? false, # Whether to include protoc for prost/tonic code generation
 }:
 
 let
@@ -34,18 +38,20 @@ let
 
   llvmToolsExtensions = if withLlvmTools then [ "llvm-tools-preview" ] else [ ];
 
-  # Use provided Rust toolchain or default from rust-toolchain.toml or stable
-  defaultRustToolchain =
-    if rustToolchainFile != null then
-      (pkgs.rust-bin.fromRustupToolchainFile rustToolchainFile).override {
-        targets = [ cargoTarget ];
-        extensions = llvmToolsExtensions;
-      }
-    else
-      (pkgs.rust-bin.stable.latest.default).override {
-        targets = [ cargoTarget ];
-        extensions = llvmToolsExtensions;
-      };
+  # Use provided Rust toolchain or resolve it like the builders do
+  # (rust-toolchain.toml, pinned version/channel, or latest stable)
+  rustToolchainSpec = import ./rust-toolchain.nix {
+    inherit
+      rustToolchainFile
+      channel
+      rustVersion
+      nightlyDate
+      ;
+  };
+  defaultRustToolchain = rustToolchainSpec.select pkgs.rust-bin {
+    targets = [ cargoTarget ];
+    extensions = llvmToolsExtensions;
+  };
 
   finalRustToolchain = if rustToolchain != null then rustToolchain else defaultRustToolchain;
 
@@ -69,6 +75,9 @@ let
     else
       [ ];
 
+  # protoc packages (optional)
+  protocPackages = if withProtoc then [ pkgs.protobuf ] else [ ];
+
   # Treefmt packages (optional)
   treefmtPackages = if treefmtWrapper != null then [ treefmtWrapper ] ++ treefmtPrograms else [ ];
 
@@ -107,6 +116,7 @@ let
     ++ ciPackages
     ++ coveragePackages
     ++ postgresPackages
+    ++ protocPackages
     ++ treefmtPackages
     ++ linuxPackages;
 
@@ -123,17 +133,23 @@ let
   # mold is only supported on Linux, so falling back to lld on Darwin
   linker = if buildPlatform.isDarwin then "lld" else "mold";
 in
-craneLib.devShell {
-  shellHook = finalShellHook;
-  packages = allPackages;
-
-  LD_LIBRARY_PATH = pkgs.lib.makeLibraryPath (
-    [
-      pkgs.openssl
-      pkgs.curl
-    ]
-    ++ pkgs.lib.optionals pkgs.stdenv.isLinux [ pkgs.libgcc.lib ]
-  );
+craneLib.devShell (
+  {
+    shellHook = finalShellHook;
+    packages = allPackages;
 
-  CARGO_BUILD_RUSTFLAGS = "-C link-arg=-fuse-ld=${linker}";
-}
+    LD_LIBRARY_PATH = pkgs.lib.makeLibraryPath (
+      [
+        pkgs.openssl
+        pkgs.curl
+      ]
+      ++ pkgs.lib.optionals pkgs.stdenv.isLinux [ pkgs.libgcc.lib ]
+    );
+
+    CARGO_BUILD_RUSTFLAGS = "-C link-arg=-fuse-ld=${linker}";
+  }
+  // pkgs.lib.optionalAttrs withProtoc {
+    PROTOC = "${pkgs.protobuf}/bin/protoc";
+    PROTOC_INCLUDE = "${pkgs.protobuf}/include";
+  }
+)
@@ -7,6 +7,47 @@
 { lib }:
 
 rec {
+  # Named sets of additional files needed by common code generators
+  # Select them with the `presets` argument of mkSrc and mkTestSrc.
+  # Each preset maps the project root and lib.fileset to a fileset.
+  presets = {
+    # Protocol Buffers definitions compiled by prost/tonic build scripts
+    proto = { root, fs }: fs.fileFilter (file: file.hasExt "proto") root;
+
+    # sqlx offline query metadata (.sqlx/*.json) and migrations (migrations/*.sql),
+    # also of workspace members
+    sqlx =
+      { root, fs }:
+      fs.fromSource (
+        lib.cleanSourceWith {
+          src = root;
+          filter =
+            path: type:
+            let
+              dir = baseNameOf (dirOf path);
+            in
+            (type == "directory" && baseNameOf path != "target")
+            || (dir == ".sqlx" && lib.hasSuffix ".json" path)
+            || (dir == "migrations" && lib.hasSuffix ".sql" path);
+        }
+      );
+  };
+
+  # Resolve a list of preset names to their filesets
+  presetFiles =
+    {
+      root,
+      fs,
+      names,
+    }:
+    map (
+      name:
+      if presets ? ${name} then
+        presets.${name} { inherit root fs; }
+      else
+        throw "sources: unknown preset '${name}', expected one of: ${lib.concatStringsSep ", " (builtins.attrNames presets)}"
+    ) names;
+
   # Create a filtered source for dependency-only builds
   # Only includes files necessary for resolving Rust dependencies
   #
@@ -42,12 +83,14 @@ rec {
   #   fs: lib.fileset (typically lib.fileset)
   #   extraFiles: Optional list of additional files to include (default: [])
   #   extraExtensions: Optional list of additional file extensions to include (default: [])
+  #   presets: Optional list of preset names, e.g. [ "proto" ] (default: [])
   mkSrc =
     {
       root,
       fs,
       extraFiles ? [ ],
       extraExtensions ? [ ],
+      presets ? [ ],
     }:
     let
       baseExtensions = [
@@ -66,6 +109,10 @@ rec {
           # Source files
         ]
         ++ (map (ext: fs.fileFilter (file: file.hasExt ext) root) allExtensions)
+        ++ presetFiles {
+          inherit root fs;
+          names = presets;
+        }
         ++ extraFiles
       );
     in
@@ -82,6 +129,7 @@ rec {
   #   extraFiles: Optional list of additional files to include (default: [])
   #   extraExtensions: Optional list of additional file extensions to include (default: [])
   #   testDataPatterns: Optional list of glob patterns for test data files (default: [])
+  #   presets: Optional list of preset names, e.g. [ "proto" ] (default: [])
   mkTestSrc =
     {
       root,
@@ -89,6 +137,7 @@ rec {
       extraFiles ? [ ],
       extraExtensions ? [ ],
       testDataPatterns ? [ ],
+      presets ? [ ],
     }:
     let
       baseExtensions = [
@@ -107,6 +156,10 @@ rec {
           # Source files
         ]
         ++ (map (ext: fs.fileFilter (file: file.hasExt ext) root) allExtensions)
+        ++ presetFiles {
+          inherit root fs;
+          names = presets;
+        }
         ++ extraFiles
       );
     in
@@ -0,0 +1,89 @@
+# test-services.nix - Service fixtures for test derivations
+#
+# Starts services like PostgreSQL and Redis inside the build sandbox before
+# the tests run, and stops them afterwards. Services only listen on unix
+# sockets in the build directory, so parallel builds never conflict, and their
+# URLs are exported for the tests (DATABASE_URL, REDIS_URL).
+#
+# This is a low-level building block used internally by the library.
+
+{
+  lib, # Nixpkgs lib utilities
+  pkgs, # Nixpkgs package set
+  testServices, # Services to run, e.g. { postgres = { }; redis = { }; }
+}:
+let
+  # Services run on the build platform, also when cross-compiling
+  buildPkgs = pkgs.pkgsBuildHost;
+
+  servicesDir = "$TMPDIR/test-services";
+
+  postgres =
+    {
+      package ? buildPkgs.postgresql_17,
+      database ? "postgres", # Database created for the tests
+      initScripts ? [ ], # SQL files run against the database after startup
+    }:
+    {
+      start = ''
+        echo "starting PostgreSQL"
+        export PGDATA=${servicesDir}/postgres
+        export PGHOST=${servicesDir}
+        export PGUSER=postgres
+        export PGDATABASE=${database}
+        ${package}/bin/initdb -D "$PGDATA" -U postgres --auth=trust --no-locale --encoding=UTF8 > /dev/null
+        ${package}/bin/pg_ctl -D "$PGDATA" -l ${servicesDir}/postgres.log -w start \
+          -o "-c listen_addresses=\"\" -k $PGHOST"
+        ${lib.optionalString (database != "postgres") ''
+          ${package}/bin/createdb ${lib.escapeShellArg database}
+        ''}
+        ${lib.concatMapStrings (script: ''
+          ${package}/bin/psql -v ON_ERROR_STOP=1 -q -f ${script}
+        '') initScripts}
+        export DATABASE_URL="postgresql://postgres@localhost/${database}?host=$PGHOST"
+      '';
+      stop = ''
+        echo "stopping PostgreSQL"
+        ${package}/bin/pg_ctl -D "$PGDATA" -m fast -w stop
+      '';
+    };
+
+  redis =
+    {
+      package ? buildPkgs.redis,
+    }:
+    {
+      start = ''
+        echo "starting Redis"
+        ${package}/bin/redis-server --port 0 --unixsocket ${servicesDir}/redis.sock \
+          --dir ${servicesDir} --logfile ${servicesDir}/redis.log --daemonize yes
+        for _ in $(seq 50); do
+          ${package}/bin/redis-cli -s ${servicesDir}/redis.sock ping > /dev/null 2>&1 && break
+          sleep 0.1
+        done
+        ${package}/bin/redis-cli -s ${servicesDir}/redis.sock ping > /dev/null
+        export REDIS_URL="redis+unix://${servicesDir}/redis.sock"
+      '';
+      stop = ''
+        echo "stopping Redis"
+        ${package}/bin/redis-cli -s ${servicesDir}/redis.sock shutdown nosave
+      '';
+    };
+
+  serviceTypes = { inherit postgres redis; };
+
+  unknownServices = builtins.filter (name: !(serviceTypes ? ${name})) (
+    builtins.attrNames testServices
+  );
+
+  services = lib.mapAttrsToList (name: config: serviceTypes.${name} config) testServices;
+in
+assert lib.assertMsg (unknownServices == [ ])
+  "testServices: unsupported services ${lib.concatStringsSep ", " unknownServices}, expected: ${lib.concatStringsSep ", " (builtins.attrNames serviceTypes)}";
+{
+  start = ''
+    mkdir -p ${servicesDir}
+  ''
+  + lib.concatMapStrings (service: service.start) services;
+  stop = lib.concatMapStrings (service: service.stop) services;
+}
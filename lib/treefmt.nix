@@ -12,9 +12,16 @@
   globalExcludes ? [ ], # Additional global exclusions
   extraFormatters ? { }, # Additional formatter configurations
   projectRootFile ? "flake.nix", # File to identify project root
+  channel ? "stable", # Pinned toolchain channel for rustfmt
+  rustVersion ? null, # Pinned toolchain version for rustfmt
+  nightlyDate ? null, # Pinned nightly date for rustfmt
 }:
 
 let
+  # rustfmt comes from the latest nightly (for unstable options) unless a
+  # toolchain is pinned, in which case the pinned toolchain is used
+  rustToolchain = import ./rust-toolchain.nix { inherit channel rustVersion nightlyDate; };
+
   # Default global exclusions for most projects
   defaultExcludes = [
     # Binary and lock files
@@ -77,7 +84,7 @@ let
     # Rust formatting with nightly for unstable features
     programs.rustfmt.enable = true;
     settings.formatter.rustfmt = {
-      command = "${pkgs.rust-bin.selectLatestNightlyWith (toolchain: toolchain.default)}/bin/rustfmt";
+      command = "${rustToolchain.selectRustfmt pkgs.rust-bin}/bin/rustfmt";
       options = [
         "--config-path"
         "."
@@ -0,0 +1,9 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub fn transport() -> &'static str {
+    if cfg!(feature = "tls") {
+        "tls"
+    } else {
+        "plain"
+    }
+}
@@ -0,0 +1 @@
+//! Fixture crate for the `proto` source preset check.
@@ -0,0 +1 @@
+CREATE TABLE greetings (id BIGSERIAL PRIMARY KEY, text TEXT NOT NULL);
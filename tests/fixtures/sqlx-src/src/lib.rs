@@ -0,0 +1 @@
+//! Fixture crate for the `sqlx` source preset check.
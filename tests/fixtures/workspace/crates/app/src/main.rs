@@ -0,0 +1,3 @@
+fn main() {
+    println!("{}", workspace_core::greeting());
+}